@@ -1,7 +1,46 @@
+use std::collections::HashMap;
 use std::fmt;
+pub use crate::span::Span;
 
 pub type StmtBlock = Vec<AstNode>;
 pub type Param = Vec<AstNode>;
+// `'outer` in `'outer: while ...`, stored without the quote
+pub type LoopLabel = Option<String>;
+// `<T: Shape, U>`: type parameters with their interface bound
+pub type Generics = Vec<(String, Option<String>)>;
+
+/// How a function is named in the LLVM module. Functions are mangled so that
+/// overloads get distinct symbols, see `mangle`; `main` and `#[no_mangle]`
+/// functions keep their source name.
+#[derive(Debug, Clone, Default)]
+pub struct Linkage {
+    pub no_mangle: bool,
+    // filled in by the semantic pass
+    pub symbol: String,
+}
+
+// `Circle(float)`: variant name (an Ident), payload types with their spans
+pub type Variant = (AstNode, Vec<(AstType, Span)>);
+// pattern, body (`vec![TailExpr]` for `pat => expr`)
+pub type MatchArm = (Pattern, StmtBlock);
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Wildcard(Span),
+    // binds the whole value
+    Binding(String, Span),
+    // `Shape::Rect(w, _)`: enum, variant, sub-patterns (bindings or `_`), tag set by the checker
+    Variant(String, String, Vec<Pattern>, usize, Span),
+}
+
+impl Pattern {
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Wildcard(span) | Pattern::Binding(_, span) |
+            Pattern::Variant(_, _, _, _, span) => *span,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Module {
@@ -14,35 +53,101 @@ pub enum AstType {
     Float,
     Str,
     Bool,
-    // TODO: extend type: struct, enum, interface ...
+    Void,
+    // a struct or an enum
     Ext(String),
+    // the `self` receiver of a method, passed by pointer
+    Ref(Box<AstType>),
+    // `dyn Shape`: any type implementing the interface, called through a vtable
+    Dyn(String),
+    // `Pair<int>`: an instance of a generic struct with its type arguments
+    Generic(String, Vec<AstType>),
     Nil,
     Undef,
 }
 
+/// Every node carries the `Span` of the source it was parsed from. Identifiers
+/// declared with a type annotation (`a: int`) span the annotation as well.
 #[derive(Debug, Clone)]
 pub enum AstNode {
-    Int(i32),
-    Float(f32),
-    Str(String),
-    Nil,
+    Int(i64, Span),
+    Float(f32, Span),
+    Bool(bool, Span),
+    Str(String, Span),
+    Nil(Span),
 
-    Ident(String, AstType),
-    // Fn: Identifer, param: Vec<Identifer>, rtype: Ident, block<Statement>
-    FnDecl(Box<AstNode>, Param, StmtBlock),
-    // Fn: Identifer, param: Vec<Identifer>
-    FnCall(Box<AstNode>, Param),
-    // Struct: Identifer, Vec<member>
-    StructDecl(Box<AstNode>, StmtBlock),
-    BinaryOp(Box<AstNode>, Operator, Box<AstNode>, AstType),
-    UnaryOp(Operator, Box<AstNode>),
-    VarDecl(Box<AstNode>, Box<AstNode>, AstType),
-    Assignment(Box<AstNode>, Box<AstNode>),
-    // conditional, block
-    WhileStmt(Box<AstNode>, StmtBlock),
-    // conditional, T-block, F-block
-    IfStmt(Box<AstNode>, StmtBlock, StmtBlock),
-    ReturnStmt(Box<AstNode>, AstType),
+    Ident(String, AstType, Span),
+    // Fn: Identifer (typed by the return type), type params, param: Vec<Identifer>,
+    // span of the return type (empty after the params when omitted), block<Statement>
+    FnDecl(Box<AstNode>, Generics, Param, Span, StmtBlock, Linkage, Span),
+    // Fn: Identifer, param: Vec<Identifer>, symbol of the resolved overload
+    FnCall(Box<AstNode>, Param, String, Span),
+    // `p.len(..)`: receiver, method name, args, symbol of the resolved method
+    MethodCall(Box<AstNode>, String, Param, String, Span),
+    // Struct: Identifer, type params, Vec<member>
+    StructDecl(Box<AstNode>, Generics, StmtBlock, Span),
+    // Enum: Identifer, variants
+    EnumDecl(Box<AstNode>, Vec<Variant>, Span),
+    // `Shape::Circle(1.0)`: enum Identifer, variant, payload, tag (set by the checker)
+    EnumLit(Box<AstNode>, String, Param, usize, Span),
+    // scrutinee, arms, value type (Void as a statement)
+    MatchExpr(Box<AstNode>, Vec<MatchArm>, AstType, Span),
+    // a struct value converted to `dyn Interface`, inserted by the checker:
+    // value, its type name, interface
+    DynCast(Box<AstNode>, String, String, Span),
+    // method call through a vtable: receiver, interface, method slot, args
+    DynCall(Box<AstNode>, String, usize, Param, Span),
+    // `impl Shape for Point { .. }`: type Identifer, interface Identifer, FnDecls
+    // named `Point::name` once checked
+    ImplDecl(Box<AstNode>, Option<Box<AstNode>>, StmtBlock, Span),
+    // Interface: Identifer, method signatures (FnDecls without a body)
+    InterfaceDecl(Box<AstNode>, StmtBlock, Span),
+    // `Point { x: 1, y: 2 }`: struct Identifer, fields (in declaration order once checked)
+    StructLit(Box<AstNode>, Vec<(String, AstNode)>, Span),
+    // `p.x`: struct value, field name, field index and type (set by the checker)
+    FieldAccess(Box<AstNode>, String, usize, AstType, Span),
+    BinaryOp(Box<AstNode>, Operator, Box<AstNode>, AstType, Span),
+    UnaryOp(Operator, Box<AstNode>, AstType, Span),
+    VarDecl(Box<AstNode>, Box<AstNode>, AstType, Span),
+    // top-level `const`: Identifer, value (folded to a literal by the checker), type
+    ConstDecl(Box<AstNode>, Box<AstNode>, AstType, Span),
+    Assignment(Box<AstNode>, Box<AstNode>, Span),
+    // conditional, block, label
+    WhileStmt(Box<AstNode>, StmtBlock, LoopLabel, Span),
+    // block, label
+    LoopStmt(StmtBlock, LoopLabel, Span),
+    // var, start, end, inclusive, step (Nil when omitted), block, label
+    ForStmt(Box<AstNode>, Box<AstNode>, Box<AstNode>, bool, Box<AstNode>, StmtBlock, LoopLabel, Span),
+    // conditional, T-block, F-block (`[IfStmt]` for `else if`), value type (Void as a statement)
+    IfStmt(Box<AstNode>, StmtBlock, StmtBlock, AstType, Span),
+    // trailing expression of a block, without `;`
+    TailExpr(Box<AstNode>, Span),
+    ReturnStmt(Box<AstNode>, AstType, Span),
+    Break(LoopLabel, Span),
+    Continue(LoopLabel, Span),
+}
+
+impl AstNode {
+    pub fn span(&self) -> Span {
+        match self {
+            AstNode::Int(_, span) | AstNode::Float(_, span) | AstNode::Bool(_, span) |
+            AstNode::Str(_, span) | AstNode::Nil(span) |
+            AstNode::Ident(_, _, span) | AstNode::FnDecl(_, _, _, _, _, _, span) |
+            AstNode::FnCall(_, _, _, span) | AstNode::MethodCall(_, _, _, _, span) |
+            AstNode::StructDecl(_, _, _, span) | AstNode::ImplDecl(_, _, _, span) | AstNode::InterfaceDecl(_, _, span) |
+            AstNode::EnumDecl(_, _, span) | AstNode::EnumLit(_, _, _, _, span) |
+            AstNode::MatchExpr(_, _, _, span) | AstNode::DynCast(_, _, _, span) |
+            AstNode::DynCall(_, _, _, _, span) |
+            AstNode::StructLit(_, _, span) | AstNode::FieldAccess(_, _, _, _, span) |
+            AstNode::BinaryOp(_, _, _, _, span) | AstNode::UnaryOp(_, _, _, span) |
+            AstNode::VarDecl(_, _, _, span) | AstNode::ConstDecl(_, _, _, span) | AstNode::Assignment(_, _, span) |
+            AstNode::WhileStmt(_, _, _, span) | AstNode::LoopStmt(_, _, span) |
+            AstNode::ForStmt(_, _, _, _, _, _, _, span) | AstNode::IfStmt(_, _, _, _, span) |
+            AstNode::TailExpr(_, span) |
+            AstNode::ReturnStmt(_, _, span) | AstNode::Break(_, span) |
+            AstNode::Continue(_, span) => *span,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -61,50 +166,168 @@ pub enum Operator {
     SUB,
     MUL,
     DIV,
+    REM,
     OpUnknown,
 }
 
+/// Built-in type keywords are matched exactly; any other name is a user
+/// type, kept as written (`Point` and `point` are different types).
 pub fn typeof_ident(v: &String) -> AstType {
-    let v2 = v.to_lowercase();
-    match &v2[..] {
+    match &v[..] {
         "int" => AstType::Int,
         "float" => AstType::Float,
         "str" => AstType::Str,
         "bool" => AstType::Bool,
-        _ => AstType::Ext(v2),
+        _ => AstType::Ext(v.clone()),
+    }
+}
+
+/// `_D` + length-prefixed name + one code per parameter: `i` int, `f` float,
+/// `b` bool, `s` str, `S` + length-prefixed name for a struct, `D` + one for
+/// a `dyn` interface, `R` + code for a `self` receiver, or a lone `v` without
+/// parameters. An instance of a generic struct adds the codes of its type
+/// arguments between `I` and `E`. Each segment of a path is prefixed on its
+/// own. `fn foo(a: int, b: Point)` is `_D3fooiS5Point`, `Point::len(self)` is
+/// `_D5Point3lenRS5Point` and `fn bar(p: Pair<int>)` is `_D3barS4PairIiE`.
+pub fn mangle(name: &str, params: &[AstType]) -> String {
+    let mut sym = String::from("_D");
+    for seg in name.split("::") {
+        sym.push_str(&format!("{}{}", seg.len(), seg));
+    }
+    if params.is_empty() { sym.push('v'); }
+    for typ in params {
+        mangle_type(&mut sym, typ);
+    }
+    sym
+}
+
+fn mangle_type(sym: &mut String, typ: &AstType) {
+    match typ {
+        AstType::Int => sym.push('i'),
+        AstType::Float => sym.push('f'),
+        AstType::Bool => sym.push('b'),
+        AstType::Str => sym.push('s'),
+        AstType::Ext(n) => sym.push_str(&format!("S{}{}", n.len(), n)),
+        AstType::Generic(n, args) => {
+            sym.push_str(&format!("S{}{}I", n.len(), n));
+            args.iter().for_each(|t| mangle_type(sym, t));
+            sym.push('E');
+        }
+        AstType::Ref(t) => { sym.push('R'); mangle_type(sym, t); }
+        AstType::Dyn(n) => sym.push_str(&format!("D{}{}", n.len(), n)),
+        _ => unreachable!("[mangle] {:?}", typ),
+    }
+}
+
+/// Replaces the type parameters in `map` wherever a type is written in `n`;
+/// used to instantiate a generic function or struct before it is checked.
+pub fn subst_types(n: &mut AstNode, map: &HashMap<String, AstType>) {
+    let block = |b: &mut StmtBlock| b.iter_mut().for_each(|n| subst_types(n, map));
+    match n {
+        AstNode::Ident(_, typ, _) => subst_type(typ, map),
+        AstNode::FnDecl(ident, _, param, _, body, _, _) => {
+            subst_types(ident, map);
+            block(param);
+            block(body);
+        }
+        AstNode::StructDecl(_, _, fields, _) => block(fields),
+        AstNode::FnCall(_, args, _, _) | AstNode::EnumLit(_, _, args, _, _) => block(args),
+        AstNode::MethodCall(obj, _, args, _, _) => { subst_types(obj, map); block(args); }
+        AstNode::StructLit(_, fields, _) => fields.iter_mut().for_each(|f| subst_types(&mut f.1, map)),
+        AstNode::FieldAccess(val, _, _, _, _) | AstNode::UnaryOp(_, val, _, _) |
+        AstNode::TailExpr(val, _) | AstNode::ReturnStmt(val, _, _) => subst_types(val, map),
+        AstNode::BinaryOp(lhs, _, rhs, _, _) | AstNode::Assignment(lhs, rhs, _) => {
+            subst_types(lhs, map);
+            subst_types(rhs, map);
+        }
+        AstNode::VarDecl(var, val, typ, _) => {
+            subst_types(var, map);
+            subst_types(val, map);
+            subst_type(typ, map);
+        }
+        AstNode::WhileStmt(cond, body, _, _) => { subst_types(cond, map); block(body); }
+        AstNode::LoopStmt(body, _, _) => block(body),
+        AstNode::ForStmt(_, start, end, _, step, body, _, _) => {
+            subst_types(start, map);
+            subst_types(end, map);
+            subst_types(step, map);
+            block(body);
+        }
+        AstNode::IfStmt(cond, tblock, fblock, _, _) => {
+            subst_types(cond, map);
+            block(tblock);
+            block(fblock);
+        }
+        AstNode::MatchExpr(val, arms, _, _) => {
+            subst_types(val, map);
+            arms.iter_mut().for_each(|arm| block(&mut arm.1));
+        }
+        _ => (),
+    }
+}
+
+pub fn subst_type(typ: &mut AstType, map: &HashMap<String, AstType>) {
+    match typ {
+        AstType::Ext(name) => if let Some(t) = map.get(name) { *typ = t.clone(); },
+        AstType::Generic(_, args) => args.iter_mut().for_each(|t| subst_type(t, map)),
+        AstType::Ref(inner) => subst_type(inner, map),
+        _ => (),
+    }
+}
+
+// generic functions and structs are only generated through their instances
+pub fn is_generic(n: &AstNode) -> bool {
+    match n {
+        AstNode::FnDecl(_, generics, _, _, _, _, _) | AstNode::StructDecl(_, generics, _, _) => !generics.is_empty(),
+        _ => false,
     }
 }
 
 pub fn ident_name(ident: &AstNode) -> String {
     match ident {
-        AstNode::Ident(var, _) => var.clone(),
+        AstNode::Ident(var, _, _) => var.clone(),
         _ => unreachable!("UnKnown ident: {:?}", ident),
     }
 }
 
 pub fn ident_type(ident: &AstNode) -> AstType {
     match ident {
-        AstNode::Ident(_, typ) => typ.clone(),
+        AstNode::Ident(_, typ, _) => typ.clone(),
         _ => unreachable!(),
     }
 }
 
 pub fn update_ident_type(ident: &mut AstNode, typ: AstType) {
-    if let AstNode::Ident(_, ref mut _typ) = ident {
+    if let AstNode::Ident(_, ref mut _typ, _) = ident {
         *_typ = typ;
     }
 }
 
 pub fn nil_node(n: &AstNode) -> bool {
     match n {
-        AstNode::Nil => true,
+        AstNode::Nil(_) => true,
         _ => false,
     }
 }
 
 impl fmt::Display for AstType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            AstType::Int => "int".fmt(f),
+            AstType::Float => "float".fmt(f),
+            AstType::Str => "str".fmt(f),
+            AstType::Bool => "bool".fmt(f),
+            AstType::Void => "void".fmt(f),
+            AstType::Ext(name) => name.fmt(f),
+            AstType::Generic(name, args) => {
+                let args: Vec<String> = args.iter().map(|t| t.to_string()).collect();
+                write!(f, "{}<{}>", name, args.join(", "))
+            }
+            AstType::Ref(typ) => write!(f, "&{}", typ),
+            AstType::Dyn(iface) => write!(f, "dyn {}", iface),
+            AstType::Nil => "nil".fmt(f),
+            AstType::Undef => "{unknown}".fmt(f),
+        }
     }
 }
 
@@ -122,6 +345,14 @@ pub fn is_logic_op(op: Operator) -> bool {
     }
 }
 
+pub fn is_compare_op(op: Operator) -> bool {
+    match op {
+        Operator::EQ | Operator::NE | Operator::LE |
+        Operator::GE | Operator::LT | Operator::GT => true,
+        _ => false,
+    }
+}
+
 impl fmt::Display for Operator {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let s = match *self {
@@ -139,6 +370,7 @@ impl fmt::Display for Operator {
             Operator::SUB => "-",
             Operator::MUL => "*",
             Operator::DIV => "/",
+            Operator::REM => "%",
             _ => "UnKnown",
         };
         s.fmt(f)
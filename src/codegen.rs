@@ -6,6 +6,8 @@ use self::llvm::prelude::*;
 
 use llvm::LLVMIntPredicate::*;
 use llvm::LLVMRealPredicate::*;
+use llvm::LLVMTypeKind;
+use llvm::LLVMOpcode;
 use std::ffi::CString;
 use std::ptr;
 use crate::ast::*;
@@ -21,10 +23,23 @@ pub struct LLVMGenerator {
     pub builder: LLVMBuilderRef,
 
     pub structs: TypeTable,
+    // field types of each struct and payload types of each enum variant, for enum layouts
+    pub struct_fields: HashMap<String, Vec<AstType>>,
+    pub enums: HashMap<String, Vec<Vec<AstType>>>,
+    // method names of each interface in vtable order, and the vtable of each `impl Interface for Type`
+    pub interfaces: HashMap<String, Vec<String>>,
+    pub vtables: HashMap<String, LLVMValueRef>,
     pub functions: SymbolTable,
-    pub loops: Vec<(LLVMBasicBlockRef, LLVMBasicBlockRef)>,
+    // label, `continue` target, `break` target of each enclosing loop
+    pub loops: Vec<(LoopLabel, LLVMBasicBlockRef, LLVMBasicBlockRef)>,
     pub global: SymbolTable,
     pub locals: Vec<SymbolTable>,
+    // `dyn` boxes built by the current function: whether they may outlive it,
+    // and the head of the list it frees on return (null until it builds one)
+    pub dyn_escapes: bool,
+    pub dyn_boxes: LLVMValueRef,
+    // some global holds a `dyn`, so any function may store one there
+    pub dyn_globals: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -90,22 +105,22 @@ impl LLVMGenerator {
             builder: LLVMCreateBuilderInContext(_ctx),
 
             structs: HashMap::new(),
+            struct_fields: HashMap::new(),
+            enums: HashMap::new(),
+            interfaces: HashMap::new(),
+            vtables: HashMap::new(),
             functions: HashMap::new(),
             global: HashMap::new(),
             locals: Vec::new(),
             loops: Vec::new(),
+            dyn_escapes: false,
+            dyn_boxes: ptr::null_mut(),
+            dyn_globals: false,
         }
     }
 
     pub unsafe fn run(&mut self, name: &String, module: &Vec<AstNode>) {
-        for item in module {
-            match item {
-                AstNode::FnDecl(_, _, _) => self.gen_fndecl(item.clone()),
-                AstNode::StructDecl(_, _,) => self.gen_struct(item.clone()),
-                // AstNode::VarDecl(_, _, _) => self.gen_vardecl(&item, true),
-                _ => (),
-            }
-        }
+        self.gen_module(module);
 
         let mut module_ir = convert_cstring(LLVMPrintModuleToString(self.module));
 
@@ -125,6 +140,43 @@ impl LLVMGenerator {
         LLVMContextDispose(self.ctx);
     }
 
+    pub unsafe fn gen_module(&mut self, module: &Vec<AstNode>) {
+        // struct types and function prototypes come first, so any item can use
+        // one defined further down the file
+        for item in module {
+            match item {
+                AstNode::StructDecl(_, _, _, _) if !is_generic(item) => self.declare_struct(item),
+                AstNode::EnumDecl(_, _, _) => self.declare_enum(item),
+                AstNode::InterfaceDecl(_, _, _) => self.declare_interface(item),
+                _ => (),
+            }
+        }
+        for item in module {
+            match item {
+                AstNode::FnDecl(_, _, _, _, _, _, _) if !is_generic(item) => self.declare_fn(item),
+                AstNode::ImplDecl(_, _, fns, _) => fns.iter().filter(|f| !is_generic(f)).for_each(|f| self.declare_fn(f)),
+                AstNode::StructDecl(_, _, _, _) if !is_generic(item) => self.gen_struct(item.clone()),
+                AstNode::EnumDecl(_, _, _) => self.gen_enum(item),
+                AstNode::InterfaceDecl(_, _, _) => self.gen_interface(item),
+                _ => (),
+            }
+        }
+        for item in module {
+            match item {
+                AstNode::VarDecl(_, _, _, _) | AstNode::ConstDecl(_, _, _, _) => self.gen_global(item),
+                AstNode::ImplDecl(_, Some(_), _, _) => self.gen_vtable(item),
+                _ => (),
+            }
+        }
+        for item in module {
+            match item {
+                AstNode::FnDecl(_, _, _, _, _, _, _) if !is_generic(item) => self.gen_fndecl(item.clone()),
+                AstNode::ImplDecl(_, _, fns, _) => fns.iter().filter(|f| !is_generic(f)).for_each(|f| self.gen_fndecl(f.clone())),
+                _ => (),
+            }
+        }
+    }
+
     fn enter_scope(&mut self) {
         self.locals.push(HashMap::new());
     }
@@ -143,39 +195,201 @@ impl LLVMGenerator {
         self.global.get(var).cloned()
     }
 
-    unsafe fn gen_fndecl(&mut self, n: AstNode) {
-        if let AstNode::FnDecl(ident, param, block) = n {
-            let function_name = ident_name(&ident);
+    unsafe fn declare_fn(&mut self, n: &AstNode) {
+        if let AstNode::FnDecl(ident, _, param, _, _, link, _) = n {
             let function_type = {
                 let return_type = self.typeof_llvm(ident_type(&ident.clone()));
                 let mut param_types = self.gen_param_type(&param);
                 LLVMFunctionType(return_type, param_types.as_mut_ptr(), param_types.len() as u32, 0)
             };
-            let function = LLVMAddFunction(self.module, function_name.into_bytes().as_ptr() as *const _, function_type);
+            let cname = CString::new(link.symbol.clone()).unwrap();
+            let function = LLVMAddFunction(self.module, cname.as_ptr(), function_type);
+            self.functions.insert(link.symbol.clone(), ir_ref!(function));
+        }
+    }
+
+    unsafe fn gen_fndecl(&mut self, n: AstNode) {
+        if let AstNode::FnDecl(ident, _, param, _, block, link, _) = n {
+            let function = self.functions[&link.symbol].val;
             let entry = CString::new("entry").unwrap();
-            self.functions.insert(ident_name(&ident), ir_ref!(function));
             self.enter_scope();
             let bb = LLVMAppendBasicBlockInContext(self.ctx, function, entry.as_ptr());
             LLVMPositionBuilderAtEnd(self.builder, bb);
+            // a `dyn` leaves the function through its result, `self` or a global
+            self.dyn_escapes = self.dyn_globals || self.holds_dyn(&ident_type(&ident)) ||
+                param.iter().any(|p| matches!(ident_type(p), AstType::Ref(_)) && self.holds_dyn(&ident_type(p)));
             self.alloc_param(function, &param);
             self.gen_block(&block);
-            if LLVMGetBasicBlockTerminator(bb).is_null() {
+            if LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(self.builder)).is_null() {
                 self.gen_default_return(ident_type(&ident.clone()));
             }
+            if !self.dyn_boxes.is_null() {
+                self.free_boxes_on_return(function);
+                self.dyn_boxes = ptr::null_mut();
+            }
             self.leave_scope();
         }
     }
 
-    unsafe fn gen_struct(&mut self, n: AstNode) {
-        if let AstNode::StructDecl(ident, block) = n {
+    // every `ret` of `function` first frees the boxes listed in `dyn_boxes`
+    unsafe fn free_boxes_on_return(&mut self, function: LLVMValueRef) {
+        let free_boxes = self.free_boxes_fn();
+        let mut bb = LLVMGetFirstBasicBlock(function);
+        while !bb.is_null() {
+            let term = LLVMGetBasicBlockTerminator(bb);
+            if !term.is_null() && LLVMGetInstructionOpcode(term) == LLVMOpcode::LLVMRet {
+                LLVMPositionBuilderBefore(self.builder, term);
+                let mut args = [LLVMBuildLoad(self.builder, self.dyn_boxes, c_str!(""))];
+                LLVMBuildCall(self.builder, free_boxes, args.as_mut_ptr(), 1, c_str!(""));
+            }
+            bb = LLVMGetNextBasicBlock(bb);
+        }
+    }
+
+    // an opaque named struct, its body is set by `gen_struct`
+    unsafe fn declare_struct(&mut self, n: &AstNode) {
+        if let AstNode::StructDecl(ident, _, fields, _) = n {
             let cname = CString::new(ident_name(&ident)).unwrap();
-            let mut member: Vec<LLVMTypeRef> = block.into_iter().map(|e| self.typeof_llvm(ident_type(&e))).collect();
             let sty = LLVMStructCreateNamed(self.ctx, cname.as_ptr());
+            self.push_struct(ident_type(&ident).to_string(), sty);
+            self.struct_fields.insert(ident_name(&ident), fields.iter().map(ident_type).collect());
+        }
+    }
+
+    unsafe fn declare_enum(&mut self, n: &AstNode) {
+        if let AstNode::EnumDecl(ident, variants, _) = n {
+            let cname = CString::new(ident_name(&ident)).unwrap();
+            let ety = LLVMStructCreateNamed(self.ctx, cname.as_ptr());
+            self.push_struct(ident_name(&ident), ety);
+            self.enums.insert(ident_name(&ident), variants.iter().map(|v| v.1.iter().map(|t| t.0.clone()).collect()).collect());
+        }
+    }
+
+    // `dyn Shape` is a fat pointer `{ i8* data, Shape.vtable* }`, both bodies are set by `gen_interface`
+    unsafe fn declare_interface(&mut self, n: &AstNode) {
+        if let AstNode::InterfaceDecl(ident, sigs, _) = n {
+            let name = ident_name(&ident);
+            let fat = CString::new(format!("dyn.{}", name)).unwrap();
+            let vtable = CString::new(format!("{}.vtable", name)).unwrap();
+            self.push_struct(AstType::Dyn(name.clone()).to_string(), LLVMStructCreateNamed(self.ctx, fat.as_ptr()));
+            self.push_struct(format!("{}.vtable", name), LLVMStructCreateNamed(self.ctx, vtable.as_ptr()));
+            self.interfaces.insert(name, sigs.iter().map(|f| match f {
+                AstNode::FnDecl(fident, _, _, _, _, _, _) => ident_name(fident),
+                _ => unreachable!("[declare_interface]: {:?}", f),
+            }).collect());
+        }
+    }
+
+    // a vtable slot takes the receiver as an untyped pointer
+    unsafe fn gen_interface(&mut self, n: &AstNode) {
+        if let AstNode::InterfaceDecl(ident, sigs, _) = n {
+            let name = ident_name(&ident);
+            let data = LLVMPointerType(LLVMInt8TypeInContext(self.ctx), 0);
+            let mut slots = Vec::new();
+            for f in sigs {
+                if let AstNode::FnDecl(fident, _, param, _, _, _, _) = f {
+                    let ret = self.typeof_llvm(ident_type(fident));
+                    let mut params = vec![data];
+                    params.extend(self.gen_param_type(&param[1..].to_vec()));
+                    let fty = LLVMFunctionType(ret, params.as_mut_ptr(), params.len() as u32, 0);
+                    slots.push(LLVMPointerType(fty, 0));
+                }
+            }
+            let vtable = self.structs[&format!("{}.vtable", name)];
+            LLVMStructSetBody(vtable, slots.as_mut_ptr(), slots.len() as u32, 0);
+            let mut member = vec![data, LLVMPointerType(vtable, 0)];
+            LLVMStructSetBody(self.structs[&AstType::Dyn(name).to_string()], member.as_mut_ptr(), member.len() as u32, 0);
+            return ;
+        }
+        unreachable!("[gen_interface]: {:?}", n);
+    }
+
+    // constant `vtable.Type.Interface` with the methods of the impl in interface order
+    unsafe fn gen_vtable(&mut self, n: &AstNode) {
+        if let AstNode::ImplDecl(ident, Some(iface), fns, _) = n {
+            let (tname, iname) = (ident_name(&ident), ident_name(&iface));
+            let vtable = self.structs[&format!("{}.vtable", iname)];
+            let mut slots = Vec::new();
+            for (idx, method) in self.interfaces[&iname].clone().iter().enumerate() {
+                let path = format!("{}::{}", tname, method);
+                let symbol = fns.iter().find_map(|f| match f {
+                    AstNode::FnDecl(fident, _, _, _, _, link, _) if ident_name(fident) == path => Some(link.symbol.clone()),
+                    _ => None,
+                }).expect("[gen_vtable] checked by the conformance check");
+                let slot_ty = LLVMStructGetTypeAtIndex(vtable, idx as u32);
+                slots.push(LLVMConstBitCast(self.functions[&symbol].val, slot_ty));
+            }
+            let cname = CString::new(format!("vtable.{}.{}", tname, iname)).unwrap();
+            let global = LLVMAddGlobal(self.module, vtable, cname.as_ptr());
+            LLVMSetInitializer(global, LLVMConstNamedStruct(vtable, slots.as_mut_ptr(), slots.len() as u32));
+            LLVMSetGlobalConstant(global, 1);
+            self.vtables.insert(format!("{}.{}", tname, iname), global);
+            return ;
+        }
+        unreachable!("[gen_vtable]: {:?}", n);
+    }
+
+    // An enum is a tagged union `{ i32 tag, [N x i64] payload }`. The payload of a
+    // variant is stored as an anonymous struct of its fields, see `variant_ptr`.
+    unsafe fn gen_enum(&mut self, n: &AstNode) {
+        if let AstNode::EnumDecl(ident, _, _) = n {
+            let name = ident_name(&ident);
+            let mut member = vec![LLVMInt32TypeInContext(self.ctx), self.payload_type(&name)];
+            LLVMStructSetBody(self.structs[&name], member.as_mut_ptr(), member.len() as u32, 0);
+            return ;
+        }
+        unreachable!("[gen_enum]: {:?}", n);
+    }
+
+    unsafe fn payload_type(&self, name: &str) -> LLVMTypeRef {
+        let slots = self.enums[name].iter().map(|v| v.iter().map(|t| self.slots(t)).sum::<u32>()).max();
+        LLVMArrayType(self.i64_type(), slots.unwrap_or(0))
+    }
+
+    // size of a value in 8-byte slots; no scalar is wider than i64, so this
+    // bounds the LLVM size of any struct of them
+    fn slots(&self, typ: &AstType) -> u32 {
+        match typ {
+            AstType::Ext(name) => match self.enums.get(name) {
+                Some(variants) => 1 + variants.iter().map(|v| v.iter().map(|t| self.slots(t)).sum::<u32>()).max().unwrap_or(0),
+                None => self.struct_fields[name].iter().map(|t| self.slots(t)).sum(),
+            },
+            AstType::Generic(_, _) => self.struct_fields[&typ.to_string()].iter().map(|t| self.slots(t)).sum(),
+            AstType::Dyn(_) => 2,
+            _ => 1,
+        }
+    }
+
+    // whether a value of `typ` can hold a `dyn`, and so point into a box
+    fn holds_dyn(&self, typ: &AstType) -> bool {
+        match typ {
+            AstType::Ext(name) => match self.enums.get(name) {
+                Some(variants) => variants.iter().flatten().any(|t| self.holds_dyn(t)),
+                None => self.struct_fields[name].iter().any(|t| self.holds_dyn(t)),
+            },
+            AstType::Generic(_, _) => self.struct_fields[&typ.to_string()].iter().any(|t| self.holds_dyn(t)),
+            AstType::Ref(inner) => self.holds_dyn(inner),
+            AstType::Dyn(_) => true,
+            _ => false,
+        }
+    }
+
+    // pointer to the payload of variant `tag` of the enum at `ptr`
+    unsafe fn variant_ptr(&mut self, ptr: LLVMValueRef, ename: &str, tag: usize) -> LLVMValueRef {
+        let mut fields: Vec<LLVMTypeRef> = self.enums[ename][tag].clone().into_iter().map(|t| self.typeof_llvm(t)).collect();
+        let vty = LLVMStructTypeInContext(self.ctx, fields.as_mut_ptr(), fields.len() as u32, 0);
+        let payload = LLVMBuildStructGEP(self.builder, ptr, 1, c_str!(""));
+        LLVMBuildBitCast(self.builder, payload, LLVMPointerType(vty, 0), c_str!(""))
+    }
+
+    unsafe fn gen_struct(&mut self, n: AstNode) {
+        if let AstNode::StructDecl(ident, _, block, _) = n {
+            let mut member: Vec<LLVMTypeRef> = block.into_iter().map(|e| self.typeof_llvm(ident_type(&e))).collect();
+            let sty = self.structs[&ident_type(&ident).to_string()];
             LLVMStructSetBody(sty, member.as_mut_ptr(), member.len() as u32, 0);
 
             // let fptr = LLVMGetTypeByName(self.module, cname.as_ptr());
             // println!("cname:{:?} member: {:?} sty {:?} p:{:?}", cname, member, sty, fptr);
-            self.push_struct(ident_name(&ident), sty);
 
             return ;
         }
@@ -184,6 +398,11 @@ impl LLVMGenerator {
 
     unsafe fn alloc_param(&mut self, func: LLVMValueRef, p: &Vec<AstNode>) {
         for (idx, var) in p.iter().enumerate() {
+            // `self` already points at the caller's struct
+            if let AstType::Ref(_) = ident_type(&var) {
+                self.push_var(ident_name(&var), ir_ref!(LLVMGetParam(func, idx as u32)));
+                continue;
+            }
             let cname = CString::new(ident_name(&var)).unwrap();
             let ty = self.typeof_llvm(ident_type(&var));
             let _var = LLVMBuildAlloca(self.builder, ty, cname.as_ptr());
@@ -207,7 +426,7 @@ impl LLVMGenerator {
     }
 
     unsafe fn gen_vardecl(&mut self, var: &AstNode, global: bool) {
-        if let AstNode::VarDecl(ident, val, _) = var {
+        if let AstNode::VarDecl(ident, val, _, _) = var {
             let cname = CString::new(ident_name(&ident)).unwrap();
             let ty = self.typeof_llvm(ident_type(&ident));
             let pvar = LLVMBuildAlloca(self.builder, ty, cname.as_ptr());
@@ -219,18 +438,45 @@ impl LLVMGenerator {
         }
     }
 
+    // initializers were folded to literals by the checker, a global without one is zeroed
+    unsafe fn gen_global(&mut self, n: &AstNode) {
+        let (ident, val, constant) = match n {
+            AstNode::VarDecl(ident, val, _, _) => (ident, val, false),
+            AstNode::ConstDecl(ident, val, _, _) => (ident, val, true),
+            _ => unreachable!("[gen_global]: {:?}", n),
+        };
+        let cname = CString::new(ident_name(ident)).unwrap();
+        let ty = self.typeof_llvm(ident_type(ident));
+        let global = LLVMAddGlobal(self.module, ty, cname.as_ptr());
+        self.dyn_globals |= self.holds_dyn(&ident_type(ident));
+        let init = if nil_node(val) { LLVMConstNull(ty) } else { self.gen_value(val).val };
+        LLVMSetInitializer(global, init);
+        LLVMSetGlobalConstant(global, constant as i32);
+        self.push_global_var(ident_name(ident), ir_ref!(global));
+    }
+
     unsafe fn gen_initializer(&mut self, expr: &AstNode) -> LLVMValueRef {
         let irv = match expr {
-            AstNode::BinaryOp(_, _, _, _) => self.gen_op(expr),
+            AstNode::BinaryOp(_, _, _, _, _) => self.gen_op(expr),
             _ => self.gen_value(expr),
         };
         return self.load(&irv);
     }
 
     unsafe fn gen_return(&mut self, expr: &AstNode) {
-        if let AstNode::ReturnStmt(var, _) = expr {
+        if let AstNode::ReturnStmt(var, typ, _) = expr {
+            if nil_node(var) {
+                LLVMBuildRetVoid(self.builder);
+                return ;
+            }
+            // `return g();` in a void function calls `g` for its effects
+            if *typ == AstType::Void {
+                self.gen_value(var);
+                LLVMBuildRetVoid(self.builder);
+                return ;
+            }
             let irv = match *var.clone() {
-                AstNode::BinaryOp(_, _, _, _) => self.gen_op(var),
+                AstNode::BinaryOp(_, _, _, _, _) => self.gen_op(var),
                 _ => self.gen_value(var),
             };
             LLVMBuildRet(self.builder, self.load(&irv));
@@ -239,50 +485,306 @@ impl LLVMGenerator {
         unreachable!("[gen_return] {:?}", expr);
     }
 
+    // the checker guarantees only void functions fall off their end
     unsafe fn gen_default_return(&mut self, ty: AstType) {
-        let irv = self.llvm_default_value(ty);
-        LLVMBuildRet(self.builder, irv);
+        if ty == AstType::Void {
+            LLVMBuildRetVoid(self.builder);
+            return ;
+        }
+        LLVMBuildUnreachable(self.builder);
     }
 
     unsafe fn gen_value(&mut self, val: &AstNode) -> IRValue {
         match val {
-            AstNode::Int(v) => ir_const!(LLVMConstInt(self.i64_type(), *v as u64, 1)),
-            AstNode::Float(v) => ir_const!(LLVMConstReal(self.f64_type(), *v as f64)),
-            AstNode::FnCall(_, _) => self.gen_call(val),
-            AstNode::Ident(name, _) => self.get(name).unwrap(),
-            AstNode::BinaryOp(_, _, _, _) => self.gen_op(val),
+            AstNode::Int(v, _) => ir_const!(LLVMConstInt(self.i64_type(), *v as u64, 1)),
+            AstNode::Float(v, _) => ir_const!(LLVMConstReal(self.f64_type(), *v as f64)),
+            AstNode::Bool(v, _) => ir_const!(LLVMConstInt(self.bool_type(), *v as u64, 0)),
+            AstNode::FnCall(_, _, _, _) => self.gen_call(val),
+            AstNode::MethodCall(_, _, _, _, _) => self.gen_method_call(val),
+            AstNode::EnumLit(_, _, _, _, _) => self.gen_enum_lit(val),
+            AstNode::MatchExpr(_, _, _, _) => self.gen_match(val),
+            AstNode::Ident(name, _, _) => self.get(name).unwrap(),
+            AstNode::BinaryOp(_, _, _, _, _) => self.gen_op(val),
+            AstNode::UnaryOp(_, _, _, _) => self.gen_unary_op(val),
+            AstNode::IfStmt(_, _, _, _, _) => self.gen_if_value(val),
+            AstNode::StructLit(_, _, _) => self.gen_struct_lit(val),
+            AstNode::FieldAccess(_, _, _, _, _) => self.gen_field(val),
+            AstNode::DynCast(_, _, _, _) => self.gen_dyn_cast(val),
+            AstNode::DynCall(_, _, _, _, _) => self.gen_dyn_call(val),
             // TODO: supports String
             _ => unreachable!("{:?}", val),
         }
     }
 
+    // fields are checked and ordered as declared; constant fields fold to a constant struct
+    unsafe fn gen_struct_lit(&mut self, expr: &AstNode) -> IRValue {
+        if let AstNode::StructLit(ident, fields, _) = expr {
+            let ty = self.typeof_llvm(ident_type(ident));
+            let mut agg = LLVMGetUndef(ty);
+            for (idx, (_, val)) in fields.iter().enumerate() {
+                let v = self.gen_initializer(val);
+                agg = LLVMBuildInsertValue(self.builder, agg, v, idx as u32, c_str!(""));
+            }
+            return ir_const!(agg);
+        }
+        unreachable!("[gen_struct_lit] {:?}", expr);
+    }
+
+    // a field of something in memory is addressed with a GEP, one of a temporary is extracted
+    unsafe fn gen_field(&mut self, expr: &AstNode) -> IRValue {
+        if let AstNode::FieldAccess(obj, _, idx, _, _) = expr {
+            let base = self.gen_value(obj);
+            return match base.kind {
+                ValueKind::Ref => ir_ref!(LLVMBuildStructGEP(self.builder, base.val, *idx as u32, c_str!(""))),
+                ValueKind::Const => ir_const!(LLVMBuildExtractValue(self.builder, base.val, *idx as u32, c_str!(""))),
+            };
+        }
+        unreachable!("[gen_field] {:?}", expr);
+    }
+
     unsafe fn gen_call(&mut self, func: &AstNode) -> IRValue {
-        if let AstNode::FnCall(ident, args) = func {
-            let name = ident_name(&ident);
-            let fnptr = self.functions[&name].val;
+        if let AstNode::FnCall(_, args, symbol, _) = func {
+            let fnptr = self.functions[symbol].val;
             let mut _args: Vec<LLVMValueRef> = args.into_iter().map(|n| self.gen_initializer(n)).collect();
             return ir_const!(LLVMBuildCall(self.builder, fnptr, _args.as_mut_ptr(), _args.len() as u32, c_str!("")));
         }
         unreachable!();
     }
 
-    unsafe fn gen_conditional(&mut self, expr: &AstNode) -> LLVMValueRef {
-        match expr {
-            AstNode::BinaryOp(lhs, _, _, _) => {
-                match *lhs.clone() {
-                    AstNode::BinaryOp(_, _, _, _) => self.gen_conditional(lhs),
-                    _ => self.gen_expr_cmp(expr).val,
+    // the receiver goes by pointer
+    unsafe fn gen_method_call(&mut self, func: &AstNode) -> IRValue {
+        if let AstNode::MethodCall(obj, _, args, symbol, _) = func {
+            let fnptr = self.functions[symbol].val;
+            let mut _args = vec![self.gen_address(obj)];
+            _args.extend(args.iter().map(|n| self.gen_initializer(n)));
+            return ir_const!(LLVMBuildCall(self.builder, fnptr, _args.as_mut_ptr(), _args.len() as u32, c_str!("")));
+        }
+        unreachable!();
+    }
+
+    // The value is copied to a box on the heap, shared by every copy of the fat
+    // pointer. The function that builds it frees it on return, unless the `dyn`
+    // may outlive the function (see `gen_fndecl`); nothing tracks the last copy
+    // of such a box, so it is never freed.
+    unsafe fn gen_dyn_cast(&mut self, expr: &AstNode) -> IRValue {
+        if let AstNode::DynCast(value, tname, iface, _) = expr {
+            let val = self.gen_initializer(value);
+            let ty = self.typeof_llvm(AstType::Ext(tname.clone()));
+            let data = if self.dyn_escapes {
+                let mut size = [LLVMSizeOf(ty)];
+                LLVMBuildCall(self.builder, self.libc_malloc(), size.as_mut_ptr(), 1, c_str!(""))
+            } else {
+                self.owned_box(ty)
+            };
+            let mem = LLVMBuildBitCast(self.builder, data, LLVMPointerType(ty, 0), c_str!(""));
+            LLVMBuildStore(self.builder, val, mem);
+            let fat_ty = self.typeof_llvm(AstType::Dyn(iface.clone()));
+            let fat = LLVMBuildInsertValue(self.builder, LLVMGetUndef(fat_ty), data, 0, c_str!(""));
+            let vtable = self.vtables[&format!("{}.{}", tname, iface)];
+            return ir_const!(LLVMBuildInsertValue(self.builder, fat, vtable, 1, c_str!("")));
+        }
+        unreachable!("[gen_dyn_cast] {:?}", expr);
+    }
+
+    // A `{ i8* next, ty }` box linked into the list the current function frees
+    // on return, see `free_boxes_on_return`; returns the `i8*` to its value.
+    unsafe fn owned_box(&mut self, ty: LLVMTypeRef) -> LLVMValueRef {
+        let i8p = LLVMPointerType(LLVMInt8TypeInContext(self.ctx), 0);
+        if self.dyn_boxes.is_null() {
+            // the list head is set up first thing, before any path to a `ret`
+            let cur = LLVMGetInsertBlock(self.builder);
+            let entry = LLVMGetEntryBasicBlock(LLVMGetBasicBlockParent(cur));
+            let first = LLVMGetFirstInstruction(entry);
+            if first.is_null() {
+                LLVMPositionBuilderAtEnd(self.builder, entry);
+            } else {
+                LLVMPositionBuilderBefore(self.builder, first);
+            }
+            self.dyn_boxes = LLVMBuildAlloca(self.builder, i8p, c_str!("boxes"));
+            LLVMBuildStore(self.builder, LLVMConstNull(i8p), self.dyn_boxes);
+            LLVMPositionBuilderAtEnd(self.builder, cur);
+        }
+        let mut fields = [i8p, ty];
+        let box_ty = LLVMStructTypeInContext(self.ctx, fields.as_mut_ptr(), 2, 0);
+        let mut size = [LLVMSizeOf(box_ty)];
+        let raw = LLVMBuildCall(self.builder, self.libc_malloc(), size.as_mut_ptr(), 1, c_str!(""));
+        let node = LLVMBuildBitCast(self.builder, raw, LLVMPointerType(box_ty, 0), c_str!(""));
+        let head = LLVMBuildLoad(self.builder, self.dyn_boxes, c_str!(""));
+        LLVMBuildStore(self.builder, head, LLVMBuildStructGEP(self.builder, node, 0, c_str!("")));
+        LLVMBuildStore(self.builder, raw, self.dyn_boxes);
+        let data = LLVMBuildStructGEP(self.builder, node, 1, c_str!(""));
+        LLVMBuildBitCast(self.builder, data, i8p, c_str!(""))
+    }
+
+    // the method is loaded from the vtable and gets the data pointer as receiver
+    unsafe fn gen_dyn_call(&mut self, expr: &AstNode) -> IRValue {
+        if let AstNode::DynCall(obj, _, slot, args, _) = expr {
+            let fat = self.gen_initializer(obj);
+            let data = LLVMBuildExtractValue(self.builder, fat, 0, c_str!(""));
+            let vtable = LLVMBuildExtractValue(self.builder, fat, 1, c_str!(""));
+            let slot_ptr = LLVMBuildStructGEP(self.builder, vtable, *slot as u32, c_str!(""));
+            let fnptr = LLVMBuildLoad(self.builder, slot_ptr, c_str!(""));
+            let mut _args = vec![data];
+            _args.extend(args.iter().map(|n| self.gen_initializer(n)));
+            return ir_const!(LLVMBuildCall(self.builder, fnptr, _args.as_mut_ptr(), _args.len() as u32, c_str!("")));
+        }
+        unreachable!("[gen_dyn_call] {:?}", expr);
+    }
+
+    // where a value lives, a temporary is spilled to the stack first
+    unsafe fn gen_address(&mut self, expr: &AstNode) -> LLVMValueRef {
+        let irv = self.gen_value(expr);
+        match irv.kind {
+            ValueKind::Ref => irv.val,
+            ValueKind::Const => {
+                let tmp = LLVMBuildAlloca(self.builder, LLVMTypeOf(irv.val), c_str!(""));
+                LLVMBuildStore(self.builder, irv.val, tmp);
+                tmp
+            }
+        }
+    }
+
+    // a variant without fields is a constant, so it can also initialize a global
+    unsafe fn gen_enum_lit(&mut self, expr: &AstNode) -> IRValue {
+        if let AstNode::EnumLit(ident, _, args, tag, _) = expr {
+            let ename = ident_name(ident);
+            let ty = self.typeof_llvm(ident_type(ident));
+            let tagval = LLVMConstInt(LLVMInt32TypeInContext(self.ctx), *tag as u64, 0);
+            if args.is_empty() {
+                let mut vals = vec![tagval, LLVMConstNull(self.payload_type(&ename))];
+                return ir_const!(LLVMConstNamedStruct(ty, vals.as_mut_ptr(), vals.len() as u32));
+            }
+            let tmp = LLVMBuildAlloca(self.builder, ty, c_str!(""));
+            LLVMBuildStore(self.builder, tagval, LLVMBuildStructGEP(self.builder, tmp, 0, c_str!("")));
+            let payload = self.variant_ptr(tmp, &ename, *tag);
+            for (idx, arg) in args.iter().enumerate() {
+                let v = self.gen_initializer(arg);
+                LLVMBuildStore(self.builder, v, LLVMBuildStructGEP(self.builder, payload, idx as u32, c_str!("")));
+            }
+            return ir_const!(LLVMBuildLoad(self.builder, tmp, c_str!("")));
+        }
+        unreachable!("[gen_enum_lit] {:?}", expr);
+    }
+
+    // A switch on the tag. Variant arms become cases, a catch-all arm the
+    // default; arms the checker found unreachable are skipped. Like `if`, a
+    // `match` value is a phi over the arms that do not diverge.
+    unsafe fn gen_match(&mut self, expr: &AstNode) -> IRValue {
+        if let AstNode::MatchExpr(val, arms, typ, _) = expr {
+            let ptr = self.gen_address(val);
+            let tag = LLVMBuildLoad(self.builder, LLVMBuildStructGEP(self.builder, ptr, 0, c_str!("")), c_str!("tag"));
+
+            let parent = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+            let dblock = LLVMAppendBasicBlock(parent, c_str!("match:default"));
+            let mblock = LLVMAppendBasicBlock(parent, c_str!("match:merge"));
+            let switch = LLVMBuildSwitch(self.builder, tag, dblock, arms.len() as u32);
+
+            let mut cases = Vec::new();
+            let mut catch_all = false;
+            let mut merged = false;
+            let mut values = Vec::new();
+            let mut blocks = Vec::new();
+            for (pat, body) in arms {
+                let block = match pat {
+                    _ if catch_all => continue,
+                    Pattern::Variant(_, _, _, tag, _) => {
+                        if cases.contains(tag) { continue; }
+                        cases.push(*tag);
+                        let block = LLVMAppendBasicBlock(parent, c_str!("match:arm"));
+                        LLVMAddCase(switch, LLVMConstInt(LLVMInt32TypeInContext(self.ctx), *tag as u64, 0), block);
+                        block
+                    }
+                    _ => { catch_all = true; dblock }
+                };
+                LLVMMoveBasicBlockAfter(block, LLVMGetInsertBlock(self.builder));
+                LLVMPositionBuilderAtEnd(self.builder, block);
+                self.enter_scope();
+                self.bind_pattern(pat, ptr);
+                if *typ == AstType::Void {
+                    self.gen_block(body);
+                    if LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(self.builder)).is_null() {
+                        LLVMBuildBr(self.builder, mblock);
+                        merged = true;
+                    }
+                } else if let Some(val) = self.gen_block_value(body) {
+                    values.push(val);
+                    blocks.push(LLVMGetInsertBlock(self.builder));
+                    LLVMBuildBr(self.builder, mblock);
                 }
-            },
-            _ => self.gen_value(expr).val,
+                self.leave_scope();
+            }
+            if !catch_all {
+                // the arms are exhaustive, no other tag exists
+                LLVMMoveBasicBlockAfter(dblock, LLVMGetInsertBlock(self.builder));
+                LLVMPositionBuilderAtEnd(self.builder, dblock);
+                LLVMBuildUnreachable(self.builder);
+            }
+
+            if *typ == AstType::Void {
+                if merged {
+                    LLVMMoveBasicBlockAfter(mblock, LLVMGetInsertBlock(self.builder));
+                    LLVMPositionBuilderAtEnd(self.builder, mblock);
+                } else {
+                    // every arm diverges, stay in a terminated block
+                    LLVMDeleteBasicBlock(mblock);
+                }
+                return ir_const!(LLVMGetUndef(self.i64_type()));
+            }
+            LLVMMoveBasicBlockAfter(mblock, LLVMGetInsertBlock(self.builder));
+            LLVMPositionBuilderAtEnd(self.builder, mblock);
+            let ty = self.typeof_llvm(typ.clone());
+            if values.is_empty() {
+                return ir_const!(LLVMGetUndef(ty));
+            }
+            let phi = LLVMBuildPhi(self.builder, ty, c_str!(""));
+            LLVMAddIncoming(phi, values.as_mut_ptr(), blocks.as_mut_ptr(), values.len() as u32);
+            return ir_const!(phi);
         }
+        unreachable!("[gen_match] {:?}", expr);
     }
 
-    fn llvm_int_op(&mut self, op: &Operator) -> llvm::LLVMIntPredicate {
-        match op {
-            Operator::EQ => LLVMIntEQ,
-            Operator::GT => LLVMIntSGT,
-            Operator::LT => LLVMIntSLT,
+    // bindings are copies of the matched value or of its fields
+    unsafe fn bind_pattern(&mut self, pat: &Pattern, ptr: LLVMValueRef) {
+        match pat {
+            Pattern::Binding(name, _) => self.bind_copy(name, ptr),
+            Pattern::Variant(ename, _, subs, tag, _) => {
+                let payload = self.variant_ptr(ptr, ename, *tag);
+                for (idx, sub) in subs.iter().enumerate() {
+                    if let Pattern::Binding(name, _) = sub {
+                        let field = LLVMBuildStructGEP(self.builder, payload, idx as u32, c_str!(""));
+                        self.bind_copy(name, field);
+                    }
+                }
+            }
+            Pattern::Wildcard(_) => (),
+        }
+    }
+
+    unsafe fn bind_copy(&mut self, name: &String, ptr: LLVMValueRef) {
+        let val = LLVMBuildLoad(self.builder, ptr, c_str!(""));
+        let cname = CString::new(name.clone()).unwrap();
+        let var = LLVMBuildAlloca(self.builder, LLVMTypeOf(val), cname.as_ptr());
+        LLVMBuildStore(self.builder, val, var);
+        self.push_var(name.clone(), ir_ref!(var));
+    }
+
+    unsafe fn gen_conditional(&mut self, expr: &AstNode) -> LLVMValueRef {
+        self.gen_initializer(expr)
+    }
+
+    // ints compare signed, bools (i1) unsigned so that false < true
+    fn llvm_int_op(&mut self, op: &Operator, signed: bool) -> llvm::LLVMIntPredicate {
+        match (op, signed) {
+            (Operator::EQ, _) => LLVMIntEQ,
+            (Operator::NE, _) => LLVMIntNE,
+            (Operator::GT, true) => LLVMIntSGT,
+            (Operator::GE, true) => LLVMIntSGE,
+            (Operator::LT, true) => LLVMIntSLT,
+            (Operator::LE, true) => LLVMIntSLE,
+            (Operator::GT, false) => LLVMIntUGT,
+            (Operator::GE, false) => LLVMIntUGE,
+            (Operator::LT, false) => LLVMIntULT,
+            (Operator::LE, false) => LLVMIntULE,
             _ => unreachable!(),
         }
     }
@@ -290,20 +792,26 @@ impl LLVMGenerator {
     fn llvm_float_op(&mut self, op: &Operator) -> llvm::LLVMRealPredicate {
         match op {
             Operator::EQ => LLVMRealOEQ,
+            Operator::NE => LLVMRealUNE,
             Operator::GT => LLVMRealOGT,
+            Operator::GE => LLVMRealOGE,
             Operator::LT => LLVMRealOLT,
+            Operator::LE => LLVMRealOLE,
             _ => unreachable!(),
         }
     }
 
     unsafe fn gen_expr_cmp(&mut self, expr: &AstNode) -> IRValue {
-        if let AstNode::BinaryOp(lhs, op, rhs, ty) = expr {
-            let lval = self.gen_value(lhs);
-            let rval = self.gen_value(rhs);
-            let val = match ty {
-                AstType::Float => LLVMBuildFCmp(self.builder, self.llvm_float_op(op), self.load(&lval), self.load(&rval), c_str!("")),
-                AstType::Int => LLVMBuildICmp(self.builder, self.llvm_int_op(op), self.load(&lval), self.load(&rval), c_str!("")),
-                _ => unreachable!(),
+        if let AstNode::BinaryOp(lhs, op, rhs, _, _) = expr {
+            let lval = self.gen_initializer(lhs);
+            let rval = self.gen_initializer(rhs);
+            // the node is typed bool, so pick the predicate from the operands
+            let val = match LLVMGetTypeKind(LLVMTypeOf(lval)) {
+                LLVMTypeKind::LLVMFloatTypeKind => LLVMBuildFCmp(self.builder, self.llvm_float_op(op), lval, rval, c_str!("")),
+                _ => {
+                    let signed = LLVMGetIntTypeWidth(LLVMTypeOf(lval)) > 1;
+                    LLVMBuildICmp(self.builder, self.llvm_int_op(op, signed), lval, rval, c_str!(""))
+                }
             };
             return ir_const!(val);
         }
@@ -318,11 +826,10 @@ impl LLVMGenerator {
     }
 
     unsafe fn gen_op(&mut self, expr: &AstNode) -> IRValue {
-        if let AstNode::BinaryOp(var, op, val, ty) = expr {
-            let lhs = match *var.clone() {
-                AstNode::BinaryOp(_, _, _, _) => self.gen_op(&var),
-                _ => self.gen_value(var),
-            };
+        if let AstNode::BinaryOp(var, op, val, ty, _) = expr {
+            if is_compare_op(*op) { return self.gen_expr_cmp(expr); }
+            if is_logic_op(*op) { return self.gen_logic_op(expr); }
+            let lhs = self.gen_value(var);
             let rhs = self.gen_value(val);
             match op {
                 Operator::PLUS => {
@@ -347,7 +854,6 @@ impl LLVMGenerator {
                         _ => unreachable!("[gen_op] {:?}", ty),
                     }
                 }
-                Operator::EQ => { return self.gen_expr_cmp(expr); }
                 Operator::MUL => {
                     match ty {
                         AstType::Float => {
@@ -359,12 +865,166 @@ impl LLVMGenerator {
                         _ => unreachable!("[gen_op] {:?}", ty),
                     }
                 }
+                Operator::DIV => {
+                    match ty {
+                        AstType::Float => {
+                            return ir_const!(LLVMBuildFDiv(self.builder, self.load(&lhs), self.load(&rhs), c_str!("")));
+                        }
+                        AstType::Int => {
+                            let (l, r) = (self.load(&lhs), self.load(&rhs));
+                            self.gen_zero_check(r);
+                            return ir_const!(LLVMBuildSDiv(self.builder, l, r, c_str!("")));
+                        }
+                        _ => unreachable!("[gen_op] {:?}", ty),
+                    }
+                }
+                Operator::REM => {
+                    match ty {
+                        AstType::Float => {
+                            return ir_const!(LLVMBuildFRem(self.builder, self.load(&lhs), self.load(&rhs), c_str!("")));
+                        }
+                        AstType::Int => {
+                            let (l, r) = (self.load(&lhs), self.load(&rhs));
+                            self.gen_zero_check(r);
+                            return ir_const!(LLVMBuildSRem(self.builder, l, r, c_str!("")));
+                        }
+                        _ => unreachable!("[gen_op] {:?}", ty),
+                    }
+                }
                 _ => unreachable!("[gen_op]: {:?} -> Operator: {:?}", expr, op),
             }
         }
         unreachable!("{:?}", expr);
     }
 
+    // `&&` and `||` only evaluate the right operand when the left one does not decide the result
+    unsafe fn gen_logic_op(&mut self, expr: &AstNode) -> IRValue {
+        if let AstNode::BinaryOp(lhs, op, rhs, _, _) = expr {
+            let lval = self.gen_initializer(lhs);
+            let lhs_block = LLVMGetInsertBlock(self.builder);
+            let parent = LLVMGetBasicBlockParent(lhs_block);
+            let rhs_block = LLVMAppendBasicBlock(parent, c_str!("logic:rhs"));
+            let merge_block = LLVMAppendBasicBlock(parent, c_str!("logic:merge"));
+
+            let short_val = match op {
+                Operator::AND => {
+                    LLVMBuildCondBr(self.builder, lval, rhs_block, merge_block);
+                    LLVMConstInt(self.bool_type(), 0, 0)
+                }
+                Operator::OR => {
+                    LLVMBuildCondBr(self.builder, lval, merge_block, rhs_block);
+                    LLVMConstInt(self.bool_type(), 1, 0)
+                }
+                _ => unreachable!("[gen_logic_op] {:?}", op),
+            };
+
+            LLVMPositionBuilderAtEnd(self.builder, rhs_block);
+            let rval = self.gen_initializer(rhs);
+            // the right operand may have opened blocks of its own
+            let rhs_end = LLVMGetInsertBlock(self.builder);
+            LLVMBuildBr(self.builder, merge_block);
+
+            LLVMPositionBuilderAtEnd(self.builder, merge_block);
+            let phi = LLVMBuildPhi(self.builder, self.bool_type(), c_str!(""));
+            let mut values = [short_val, rval];
+            let mut blocks = [lhs_block, rhs_end];
+            LLVMAddIncoming(phi, values.as_mut_ptr(), blocks.as_mut_ptr(), 2);
+            return ir_const!(phi);
+        }
+        unreachable!("{:?}", expr);
+    }
+
+    // traps at runtime when the integer divisor `val` is zero
+    unsafe fn gen_zero_check(&mut self, val: LLVMValueRef) {
+        let parent = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+        let trap_block = LLVMAppendBasicBlock(parent, c_str!("div:trap"));
+        let ok_block = LLVMAppendBasicBlock(parent, c_str!("div:ok"));
+
+        let zero = LLVMConstInt(LLVMTypeOf(val), 0, 1);
+        let is_zero = LLVMBuildICmp(self.builder, LLVMIntEQ, val, zero, c_str!(""));
+        LLVMBuildCondBr(self.builder, is_zero, trap_block, ok_block);
+
+        LLVMPositionBuilderAtEnd(self.builder, trap_block);
+        let trap = self.intrinsic_trap();
+        LLVMBuildCall(self.builder, trap, ptr::null_mut(), 0, c_str!(""));
+        LLVMBuildUnreachable(self.builder);
+
+        LLVMPositionBuilderAtEnd(self.builder, ok_block);
+    }
+
+    // `i8* malloc(i64)`, declared on first use
+    unsafe fn libc_malloc(&mut self) -> LLVMValueRef {
+        let malloc = LLVMGetNamedFunction(self.module, c_str!("malloc"));
+        if !malloc.is_null() { return malloc; }
+        let mut params = [self.i64_type()];
+        let fn_type = LLVMFunctionType(LLVMPointerType(LLVMInt8TypeInContext(self.ctx), 0), params.as_mut_ptr(), 1, 0);
+        LLVMAddFunction(self.module, c_str!("malloc"), fn_type)
+    }
+
+    // `void free(i8*)`, declared on first use
+    unsafe fn libc_free(&mut self) -> LLVMValueRef {
+        let free = LLVMGetNamedFunction(self.module, c_str!("free"));
+        if !free.is_null() { return free; }
+        let mut params = [LLVMPointerType(LLVMInt8TypeInContext(self.ctx), 0)];
+        let fn_type = LLVMFunctionType(LLVMVoidTypeInContext(self.ctx), params.as_mut_ptr(), 1, 0);
+        LLVMAddFunction(self.module, c_str!("free"), fn_type)
+    }
+
+    // `void do.free_boxes(i8*)` frees a list of boxes built by `owned_box`,
+    // defined on first use
+    unsafe fn free_boxes_fn(&mut self) -> LLVMValueRef {
+        let function = LLVMGetNamedFunction(self.module, c_str!("do.free_boxes"));
+        if !function.is_null() { return function; }
+        let i8p = LLVMPointerType(LLVMInt8TypeInContext(self.ctx), 0);
+        let mut params = [i8p];
+        let fn_type = LLVMFunctionType(LLVMVoidTypeInContext(self.ctx), params.as_mut_ptr(), 1, 0);
+        let function = LLVMAddFunction(self.module, c_str!("do.free_boxes"), fn_type);
+        let cur = LLVMGetInsertBlock(self.builder);
+        let entry = LLVMAppendBasicBlockInContext(self.ctx, function, c_str!("entry"));
+        let cond = LLVMAppendBasicBlockInContext(self.ctx, function, c_str!("cond"));
+        let body = LLVMAppendBasicBlockInContext(self.ctx, function, c_str!("body"));
+        let done = LLVMAppendBasicBlockInContext(self.ctx, function, c_str!("done"));
+        LLVMPositionBuilderAtEnd(self.builder, entry);
+        LLVMBuildBr(self.builder, cond);
+        LLVMPositionBuilderAtEnd(self.builder, cond);
+        let node = LLVMBuildPhi(self.builder, i8p, c_str!("node"));
+        LLVMBuildCondBr(self.builder, LLVMBuildIsNull(self.builder, node, c_str!("")), done, body);
+        LLVMPositionBuilderAtEnd(self.builder, body);
+        let link = LLVMBuildBitCast(self.builder, node, LLVMPointerType(i8p, 0), c_str!(""));
+        let next = LLVMBuildLoad(self.builder, link, c_str!("next"));
+        let mut args = [node];
+        LLVMBuildCall(self.builder, self.libc_free(), args.as_mut_ptr(), 1, c_str!(""));
+        LLVMBuildBr(self.builder, cond);
+        let mut vals = [LLVMGetParam(function, 0), next];
+        let mut blocks = [entry, body];
+        LLVMAddIncoming(node, vals.as_mut_ptr(), blocks.as_mut_ptr(), 2);
+        LLVMPositionBuilderAtEnd(self.builder, done);
+        LLVMBuildRetVoid(self.builder);
+        LLVMPositionBuilderAtEnd(self.builder, cur);
+        function
+    }
+
+    unsafe fn intrinsic_trap(&mut self) -> LLVMValueRef {
+        let trap = LLVMGetNamedFunction(self.module, c_str!("llvm.trap"));
+        if !trap.is_null() { return trap; }
+        let fn_type = LLVMFunctionType(LLVMVoidTypeInContext(self.ctx), ptr::null_mut(), 0, 0);
+        LLVMAddFunction(self.module, c_str!("llvm.trap"), fn_type)
+    }
+
+    unsafe fn gen_unary_op(&mut self, expr: &AstNode) -> IRValue {
+        if let AstNode::UnaryOp(op, val, ty, _) = expr {
+            let v = self.gen_initializer(val);
+            let res = match (op, ty) {
+                (Operator::SUB, AstType::Float) => LLVMBuildFNeg(self.builder, v, c_str!("")),
+                (Operator::SUB, AstType::Int) => LLVMBuildNeg(self.builder, v, c_str!("")),
+                (Operator::NOT, AstType::Bool) => LLVMBuildNot(self.builder, v, c_str!("")),
+                _ => unreachable!("[gen_unary_op] {:?}", expr),
+            };
+            return ir_const!(res);
+        }
+        unreachable!("{:?}", expr);
+    }
+
     unsafe fn gen_param_type(&mut self, n: &Vec<AstNode>) -> Vec<LLVMTypeRef> {
         let mut ty = Vec::new();
         for ident in n { ty.push(self.typeof_llvm(ident_type(ident))); }
@@ -374,22 +1034,43 @@ impl LLVMGenerator {
     unsafe fn gen_block(&mut self, stmts: &Vec<AstNode>) -> bool {
         let mut ret = false;
         for stmt in stmts {
+            // code after `return`, `break` or `continue` is never reached
+            if !LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(self.builder)).is_null() { break; }
             match stmt {
-                AstNode::VarDecl(_, _, _) => self.gen_vardecl(stmt, false),
-                AstNode::IfStmt(_, _, _) => self.gen_ifstmt(stmt),
-                AstNode::Assignment(_, _) => self.gen_assign(stmt),
-                AstNode::ReturnStmt(_, _) => { self.gen_return(stmt); ret = true; }
-                AstNode::WhileStmt(_, _) => self.gen_while(stmt),
-                // AstNode::StructDecl(_, _,) => self.gen_struct(stmt),
-                _ => (),
+                AstNode::VarDecl(_, _, _, _) => self.gen_vardecl(stmt, false),
+                AstNode::IfStmt(_, _, _, _, _) => self.gen_ifstmt(stmt),
+                AstNode::MatchExpr(_, _, _, _) => { self.gen_match(stmt); },
+                AstNode::TailExpr(expr, _) => { self.gen_value(expr); },
+                AstNode::Assignment(_, _, _) => self.gen_assign(stmt),
+                AstNode::ReturnStmt(_, _, _) => { self.gen_return(stmt); ret = true; }
+                AstNode::WhileStmt(_, _, _, _) => self.gen_while(stmt),
+                AstNode::LoopStmt(_, _, _) => self.gen_loop(stmt),
+                AstNode::ForStmt(_, _, _, _, _, _, _, _) => self.gen_for(stmt),
+                AstNode::Break(label, _) => { LLVMBuildBr(self.builder, self.loop_target(label).2); }
+                AstNode::Continue(label, _) => { LLVMBuildBr(self.builder, self.loop_target(label).1); }
+                // AstNode::StructDecl(_, _, _, _) => self.gen_struct(stmt),
+                // expression statement, evaluated for its side effects
+                _ => { self.gen_value(stmt); },
             }
         }
         return ret;
     }
 
+    // bodies of `if`, `while`, `loop` and `for` get their own scope, as in the checker
+    unsafe fn gen_scoped_block(&mut self, stmts: &Vec<AstNode>) -> bool {
+        self.enter_scope();
+        let ret = self.gen_block(stmts);
+        self.leave_scope();
+        ret
+    }
+
     unsafe fn gen_assign(&mut self, stmt: &AstNode) {
-        if let AstNode::Assignment(var, val) = stmt {
-            let _var = self.get(&ident_name(var)).unwrap();
+        if let AstNode::Assignment(var, val, _) = stmt {
+            // a field of a variable is a `Ref` to it, see `gen_field`
+            let _var = match **var {
+                AstNode::FieldAccess(_, _, _, _, _) => self.gen_field(var),
+                _ => self.get(&ident_name(var)).unwrap(),
+            };
             LLVMBuildStore(self.builder, self.gen_initializer(val), _var.val);
             return ;
         }
@@ -397,7 +1078,7 @@ impl LLVMGenerator {
     }
 
     unsafe fn gen_while(&mut self, stmt: &AstNode) {
-        if let AstNode::WhileStmt(cond, body) = stmt {
+        if let AstNode::WhileStmt(cond, body, label, _) = stmt {
 
             let parent = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
             let cond_block = LLVMAppendBasicBlock(parent, c_str!("while:cond"));
@@ -408,11 +1089,11 @@ impl LLVMGenerator {
             LLVMPositionBuilderAtEnd(self.builder, cond_block);
             let condval = self.gen_conditional(cond);
             LLVMBuildCondBr(self.builder, condval, body_block, merge_block);
-            self.loops.push((cond_block, merge_block));
+            self.loops.push((label.clone(), cond_block, merge_block));
             // move to body block
             LLVMMoveBasicBlockAfter(body_block, LLVMGetInsertBlock(self.builder));
             LLVMPositionBuilderAtEnd(self.builder, body_block);
-            self.gen_block(body);
+            self.gen_scoped_block(body);
             if LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(self.builder)).is_null() {
                 LLVMBuildBr(self.builder, cond_block);
             }
@@ -427,8 +1108,90 @@ impl LLVMGenerator {
         unreachable!("[gen_while]: {:?}", stmt);
     }
 
+    unsafe fn gen_loop(&mut self, stmt: &AstNode) {
+        if let AstNode::LoopStmt(body, label, _) = stmt {
+            let parent = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+            let body_block = LLVMAppendBasicBlock(parent, c_str!("loop:body"));
+            let merge_block = LLVMAppendBasicBlock(parent, c_str!("loop:merge"));
+
+            LLVMBuildBr(self.builder, body_block);
+            self.loops.push((label.clone(), body_block, merge_block));
+            LLVMPositionBuilderAtEnd(self.builder, body_block);
+            self.gen_scoped_block(body);
+            if LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(self.builder)).is_null() {
+                LLVMBuildBr(self.builder, body_block);
+            }
+            self.loops.pop();
+            // only reachable through `break`
+            LLVMMoveBasicBlockAfter(merge_block, LLVMGetInsertBlock(self.builder));
+            LLVMPositionBuilderAtEnd(self.builder, merge_block);
+            return ;
+        }
+        unreachable!("[gen_loop]: {:?}", stmt);
+    }
+
+    // bounds and step are evaluated once, before the first iteration
+    unsafe fn gen_for(&mut self, stmt: &AstNode) {
+        if let AstNode::ForStmt(var, start, end, inclusive, step, body, label, _) = stmt {
+            let parent = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+            let cond_block = LLVMAppendBasicBlock(parent, c_str!("for:cond"));
+            let body_block = LLVMAppendBasicBlock(parent, c_str!("for:body"));
+            let step_block = LLVMAppendBasicBlock(parent, c_str!("for:step"));
+            let merge_block = LLVMAppendBasicBlock(parent, c_str!("for:merge"));
+
+            self.enter_scope();
+            let cname = CString::new(ident_name(var)).unwrap();
+            let pvar = LLVMBuildAlloca(self.builder, self.i64_type(), cname.as_ptr());
+            self.push_var(ident_name(var), ir_ref!(pvar));
+            LLVMBuildStore(self.builder, self.gen_initializer(start), pvar);
+            let endval = self.gen_initializer(end);
+            let stepval = if nil_node(step) {
+                LLVMConstInt(self.i64_type(), 1, 1)
+            } else {
+                self.gen_initializer(step)
+            };
+            LLVMBuildBr(self.builder, cond_block);
+
+            LLVMPositionBuilderAtEnd(self.builder, cond_block);
+            let pred = if *inclusive { llvm::LLVMIntPredicate::LLVMIntSLE } else { llvm::LLVMIntPredicate::LLVMIntSLT };
+            let cur = LLVMBuildLoad(self.builder, pvar, c_str!(""));
+            let condval = LLVMBuildICmp(self.builder, pred, cur, endval, c_str!(""));
+            LLVMBuildCondBr(self.builder, condval, body_block, merge_block);
+
+            self.loops.push((label.clone(), step_block, merge_block));
+            LLVMPositionBuilderAtEnd(self.builder, body_block);
+            self.gen_scoped_block(body);
+            if LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(self.builder)).is_null() {
+                LLVMBuildBr(self.builder, step_block);
+            }
+            self.loops.pop();
+
+            LLVMMoveBasicBlockAfter(step_block, LLVMGetInsertBlock(self.builder));
+            LLVMPositionBuilderAtEnd(self.builder, step_block);
+            let cur = LLVMBuildLoad(self.builder, pvar, c_str!(""));
+            let next = LLVMBuildAdd(self.builder, cur, stepval, c_str!(""));
+            LLVMBuildStore(self.builder, next, pvar);
+            LLVMBuildBr(self.builder, cond_block);
+            self.leave_scope();
+
+            LLVMMoveBasicBlockAfter(merge_block, step_block);
+            LLVMPositionBuilderAtEnd(self.builder, merge_block);
+            return ;
+        }
+        unreachable!("[gen_for]: {:?}", stmt);
+    }
+
+    // innermost loop, or the one carrying `label`
+    fn loop_target(&self, label: &LoopLabel) -> (LoopLabel, LLVMBasicBlockRef, LLVMBasicBlockRef) {
+        let found = match label {
+            None => self.loops.last(),
+            Some(_) => self.loops.iter().rev().find(|l| l.0 == *label),
+        };
+        found.cloned().unwrap()
+    }
+
     unsafe fn gen_ifstmt(&mut self, stmt: &AstNode) {
-        if let AstNode::IfStmt(cond, tstmt, fstmt) = stmt {
+        if let AstNode::IfStmt(cond, tstmt, fstmt, _, _) = stmt {
             let condval = self.gen_conditional(cond);
 
             let current = LLVMGetInsertBlock(self.builder);
@@ -442,8 +1205,8 @@ impl LLVMGenerator {
             LLVMMoveBasicBlockAfter(tblock, LLVMGetInsertBlock(self.builder));
             LLVMPositionBuilderAtEnd(self.builder, tblock);
             let mut then_term = true;
-            self.gen_block(tstmt);
-            if LLVMGetBasicBlockTerminator(tblock).is_null() {
+            self.gen_scoped_block(tstmt);
+            if LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(self.builder)).is_null() {
                 LLVMBuildBr(self.builder, mblock);
                 then_term = false;
             }
@@ -452,8 +1215,8 @@ impl LLVMGenerator {
             LLVMMoveBasicBlockAfter(eblock, LLVMGetInsertBlock(self.builder));
             LLVMPositionBuilderAtEnd(self.builder, eblock);
             let mut else_term = true;
-            self.gen_block(fstmt);
-            if LLVMGetBasicBlockTerminator(eblock).is_null() {
+            self.gen_scoped_block(fstmt);
+            if LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(self.builder)).is_null() {
                 LLVMBuildBr(self.builder, mblock);
                 else_term = false;
             }
@@ -469,28 +1232,84 @@ impl LLVMGenerator {
         }
     }
 
+    // `if` in value position: each branch that does not diverge feeds a phi in the merge block
+    unsafe fn gen_if_value(&mut self, expr: &AstNode) -> IRValue {
+        if let AstNode::IfStmt(cond, tstmt, fstmt, typ, _) = expr {
+            let condval = self.gen_conditional(cond);
+
+            let parent = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+            let tblock = LLVMAppendBasicBlock(parent, c_str!("if:then"));
+            let eblock = LLVMAppendBasicBlock(parent, c_str!("if:else"));
+            let mblock = LLVMAppendBasicBlock(parent, c_str!("if:merge"));
+            LLVMBuildCondBr(self.builder, condval, tblock, eblock);
+
+            let mut values = Vec::new();
+            let mut blocks = Vec::new();
+            for &(block, stmts) in [(tblock, tstmt), (eblock, fstmt)].iter() {
+                LLVMMoveBasicBlockAfter(block, LLVMGetInsertBlock(self.builder));
+                LLVMPositionBuilderAtEnd(self.builder, block);
+                if let Some(val) = self.gen_block_value(stmts) {
+                    values.push(val);
+                    blocks.push(LLVMGetInsertBlock(self.builder));
+                    LLVMBuildBr(self.builder, mblock);
+                }
+            }
+
+            LLVMMoveBasicBlockAfter(mblock, LLVMGetInsertBlock(self.builder));
+            LLVMPositionBuilderAtEnd(self.builder, mblock);
+            let ty = self.typeof_llvm(typ.clone());
+            if values.is_empty() {
+                // both branches diverge, the merge block is never reached
+                return ir_const!(LLVMGetUndef(ty));
+            }
+            let phi = LLVMBuildPhi(self.builder, ty, c_str!(""));
+            LLVMAddIncoming(phi, values.as_mut_ptr(), blocks.as_mut_ptr(), values.len() as u32);
+            return ir_const!(phi);
+        }
+        unreachable!("[gen_if_value] {:?}", expr);
+    }
+
+    // value of the block's last expression, `None` when the block diverges
+    unsafe fn gen_block_value(&mut self, stmts: &Vec<AstNode>) -> Option<LLVMValueRef> {
+        self.enter_scope();
+        let val = self.gen_block_tail(stmts);
+        self.leave_scope();
+        val
+    }
+
+    unsafe fn gen_block_tail(&mut self, stmts: &Vec<AstNode>) -> Option<LLVMValueRef> {
+        let (last, init) = match stmts.split_last() {
+            Some(split) => split,
+            None => return None,
+        };
+        self.gen_block(&init.to_vec());
+        if !LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(self.builder)).is_null() { return None; }
+        let val = match last {
+            AstNode::TailExpr(expr, _) => self.gen_initializer(expr),
+            AstNode::IfStmt(_, _, _, _, _) | AstNode::MatchExpr(_, _, _, _) => self.gen_initializer(last),
+            _ => { self.gen_block(&vec![last.clone()]); return None; }
+        };
+        if !LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(self.builder)).is_null() { return None; }
+        Some(val)
+    }
+
     unsafe fn typeof_llvm(&mut self, t: AstType) -> LLVMTypeRef {
         match t {
             AstType::Int => LLVMInt64TypeInContext(self.ctx),
             AstType::Float => LLVMFloatTypeInContext(self.ctx),
             // TODO: AstType::Str => LLVMConstStringInContext(self.ctx),
             AstType::Bool => LLVMInt1TypeInContext(self.ctx),
+            AstType::Void => LLVMVoidTypeInContext(self.ctx),
+            // the checker reports unknown types
             AstType::Ext(name) => {
-                *self.structs.get(&name).unwrap()
+                *self.structs.get(&name).expect("[typeof_llvm] unknown type")
             },
+            AstType::Ref(typ) => LLVMPointerType(self.typeof_llvm(*typ), 0),
+            AstType::Dyn(_) | AstType::Generic(_, _) => self.structs[&t.to_string()],
             _ => LLVMInt8TypeInContext(self.ctx),
         }
     }
 
-    unsafe fn llvm_default_value(&mut self, t: AstType) -> LLVMValueRef {
-        match t {
-            AstType::Int => LLVMConstInt(self.i64_type(), 0 as u64, 1),
-            AstType::Float => LLVMConstReal(self.f64_type(), 0 as f64),
-            AstType::Bool => LLVMConstInt(self.bool_type(), 0 as u64, 0),
-            _ => LLVMConstInt(self.i64_type(), 0 as u64, 1),
-        }
-    }
-
     unsafe fn i64_type(&self) -> LLVMTypeRef {
         LLVMInt64TypeInContext(self.ctx)
     }
@@ -511,6 +1330,7 @@ fn codegen_test() {
     use crate::semantic::*;
     use crate::codegen::*;
     use crate::grammar::ModuleParser;
+    use crate::span::FileId;
     let sources = r#"
         fn foo1(a: int, b: int) -> int {
             let c = a + 1001;
@@ -547,8 +1367,9 @@ fn codegen_test() {
             }
         }
     "#;
-    let stmts = ModuleParser::new().parse(sources).unwrap();
-    let typed_ast = semantic_check(stmts);
+    let stmts = ModuleParser::new().parse(FileId(0), sources).unwrap();
+    let (typed_ast, diags) = semantic_check(stmts);
+    assert!(diags.is_empty(), "{:?}", diags);
     unsafe {
 
     let mut generator = LLVMGenerator::new();
@@ -556,3 +1377,572 @@ fn codegen_test() {
 
     }
 }
+
+// compiles `sources` in memory and returns what its `main` returns
+#[cfg(test)]
+fn eval(sources: &str) -> i64 {
+    use std::ffi::CStr;
+    use llvm::analysis::*;
+    use llvm::execution_engine::*;
+    use llvm::target::*;
+    use crate::semantic::*;
+    use crate::grammar::ModuleParser;
+    use crate::span::FileId;
+    let stmts = ModuleParser::new().parse(FileId(0), sources).unwrap();
+    let (typed_ast, diags) = semantic_check(stmts);
+    assert!(!crate::error::has_errors(&diags), "{:?}", diags);
+    unsafe {
+        let mut generator = LLVMGenerator::new();
+        generator.gen_module(&typed_ast);
+        let mut err = std::ptr::null_mut();
+        if LLVMVerifyModule(generator.module, LLVMVerifierFailureAction::LLVMReturnStatusAction, &mut err) != 0 {
+            panic!("{}", CStr::from_ptr(err).to_string_lossy());
+        }
+        LLVMLinkInMCJIT();
+        LLVM_InitializeNativeTarget();
+        LLVM_InitializeNativeAsmPrinter();
+        let mut engine = std::ptr::null_mut();
+        if LLVMCreateExecutionEngineForModule(&mut engine, generator.module, &mut err) != 0 {
+            panic!("{}", CStr::from_ptr(err).to_string_lossy());
+        }
+        let main: extern "C" fn() -> i64 = std::mem::transmute(LLVMGetFunctionAddress(engine, c_str!("main")));
+        main()
+    }
+}
+
+#[test]
+fn precedence_test() {
+    let sources = r#"
+        fn main() -> int {
+            let a = 2 + 3 * 4 - (10 - 4) * 2;
+            if (a * 2 + 1) > 4 {
+                return a * (a + 1);
+            }
+            return 0;
+        }
+    "#;
+    assert_eq!(eval(sources), 6);
+}
+
+#[test]
+fn unary_test() {
+    let sources = r#"
+        fn dec(n: int) -> int {
+            return n-1;
+        }
+
+        fn main() -> int {
+            let n = 5;
+            let m = -n;
+            let f = -1.5;
+            let min = -2147483648;
+            let g = -2147483648.5;
+            if !(m > 0) && min < -2147483647 && n-2147483647 == min + 6 {
+                if -f > 1.0 && g < -2147483000.0 {
+                    return -(n - 8) * 2 + dec(n-1);
+                }
+            }
+            return 0;
+        }
+    "#;
+    assert_eq!(eval(sources), 9);
+}
+
+#[test]
+fn arithmetic_test() {
+    let sources = r#"
+        fn main() -> int {
+            let a = 17;
+            let r = 0;
+            if a / 5 == 3 { r = r + 1; }
+            if a % 5 != 2 { r = r + 100; }
+            if -a % 5 == -2 { r = r + 2; }
+            if a >= 17 { r = r + 4; }
+            if a <= 16 { r = r + 100; }
+            if 7.5 / 2.5 >= 3.0 { r = r + 8; }
+            if 7.5 % 2.0 != 1.5 { r = r + 100; }
+            if (a > 1) != (a < 1) { r = r + 16; }
+            if (a < 1) < (a > 1) { r = r + 32; }
+            return r;
+        }
+    "#;
+    assert_eq!(eval(sources), 63);
+}
+
+#[test]
+fn short_circuit_test() {
+    let sources = r#"
+        fn main() -> int {
+            let a = 10;
+            let b = 0;
+            let r = 0;
+            if b != 0 && a / b > 1 { r = r + 100; }
+            if b == 0 || a / b > 1 { r = r + 1; }
+            if a > 0 && (b > 0 || a % 2 == 0) { r = r + 2; }
+            if !(a > 0 && b > 0) { r = r + 4; }
+            return r;
+        }
+    "#;
+    assert_eq!(eval(sources), 7);
+}
+
+#[test]
+fn bool_test() {
+    let sources = r#"
+        fn is_even(n: int) -> bool {
+            return n % 2 == 0;
+        }
+
+        fn pick(c: bool, a: int, b: int) -> int {
+            if c { return a; }
+            return b;
+        }
+
+        fn main() -> int {
+            let ok = is_even(4);
+            let flag: bool;
+            flag = ok && !is_even(3);
+            let r = 0;
+            if flag { r = r + 1; }
+            let t = true;
+            while t {
+                t = false;
+                r = r + 2;
+            }
+            if ok == true { r = r + 4; }
+            r = r + pick(r > 100, 100, 8);
+            return r;
+        }
+    "#;
+    assert_eq!(eval(sources), 15);
+}
+
+#[test]
+fn void_fn_test() {
+    let sources = r#"
+        fn check(n: int) {
+            if n == 0 { return; }
+            let m = 100 / n;
+        }
+
+        fn noop() {}
+
+        fn twice(n: int) {
+            check(n);
+            check(n)
+        }
+
+        let count = 0;
+        fn bump() { count = count + 1; }
+        fn bump_twice() {
+            bump();
+            return bump();
+        }
+
+        fn main() -> int {
+            check(0);
+            noop();
+            twice(5);
+            bump_twice();
+            return count;
+        }
+    "#;
+    assert_eq!(eval(sources), 2);
+}
+
+#[test]
+fn break_continue_test() {
+    let sources = r#"
+        fn main() -> int {
+            let i = 0;
+            let sum = 0;
+            while true {
+                i = i + 1;
+                if i > 10 { break; }
+                if i % 2 == 0 {
+                    continue;
+                    sum = sum + 1000;
+                }
+                sum = sum + i;
+            }
+            return sum;
+        }
+    "#;
+    assert_eq!(eval(sources), 25);
+}
+
+#[test]
+fn loop_for_test() {
+    let sources = r#"
+        fn main() -> int {
+            let sum = 0;
+            for i in 0..5 { sum = sum + i; }
+            for i in 1..=10 step 3 { sum = sum + i * 100; }
+            let n = 0;
+            loop {
+                n = n + 1;
+                if n == 7 { break; }
+            }
+            'outer: for i in 0..10 {
+                let j = 0;
+                while true {
+                    j = j + 1;
+                    if j > i { continue 'outer; }
+                    if i * j > 12 { break 'outer; }
+                    sum = sum + 1000;
+                }
+            }
+            return sum + n * 100000;
+        }
+    "#;
+    assert_eq!(eval(sources), 711210);
+}
+
+#[test]
+fn if_expr_test() {
+    let sources = r#"
+        fn sign(n: int) -> int {
+            if n < 0 { return -1; }
+            else if n == 0 { return 0; }
+            else { return 1; }
+        }
+        fn grade(n: int) -> int {
+            return if n >= 90 { 4 } else if n >= 80 { 3 } else if n >= 70 { 2 } else { 0 };
+        }
+        fn main() -> int {
+            let a = if sign(-5) == -1 { 10 } else { 20 };
+            let b = 0;
+            b = if a > 5 {
+                let t = a * 2;
+                t + 1
+            } else {
+                return 99;
+            };
+            let c = grade(95) * 1000 + grade(85) * 100 + grade(72) * 10 + grade(3);
+            return a + b + c + sign(0) + sign(7);
+        }
+    "#;
+    assert_eq!(eval(sources), 10 + 21 + 4320 + 1);
+}
+
+#[test]
+fn block_scope_test() {
+    let sources = r#"
+        fn main() -> int {
+            let x = 1;
+            let sum = 0;
+            if x > 0 {
+                let x = 10;
+                sum = sum + x;
+            }
+            for i in 0..3 {
+                let x = i * 100;
+                sum = sum + x;
+            }
+            return sum + x;
+        }
+    "#;
+    assert_eq!(eval(sources), 311);
+}
+
+#[test]
+fn forward_ref_test() {
+    let sources = r#"
+        fn main() -> int {
+            return is_even(10) * 10 + is_even(7);
+        }
+        fn is_even(n: int) -> int {
+            if n == 0 { return 1; }
+            return is_odd(n - 1);
+        }
+        fn is_odd(n: int) -> int {
+            if n == 0 { return 0; }
+            return is_even(n - 1);
+        }
+    "#;
+    assert_eq!(eval(sources), 10);
+}
+
+#[test]
+fn overload_test() {
+    let sources = r#"
+        fn pick(a: int) -> int { return a; }
+        fn pick(a: int, b: int) -> int { return a * b; }
+        fn pick(a: bool) -> int { if a { return 100; } return 0; }
+        #[no_mangle]
+        fn exported() -> int { return 1000; }
+        fn main() -> int {
+            return pick(3) + pick(4, 5) + pick(true) + exported();
+        }
+    "#;
+    assert_eq!(eval(sources), 1123);
+}
+
+#[test]
+fn global_test() {
+    let sources = r#"
+        const LIMIT: int = 10 * 2 - 5;
+        const HALF: float = 1.0 / 2.0;
+        const BIG: bool = LIMIT > 10 && HALF < 1.0;
+        const WIDE: int = 2147483647 * 4;
+        let counter = LIMIT + 1;
+        let total: int;
+        fn bump(n: int) {
+            counter = counter + n;
+            total = total + 1;
+        }
+        fn main() -> int {
+            bump(4);
+            bump(LIMIT);
+            if BIG && WIDE == 2147483647 * 4 { return counter * 10 + total; }
+            return 0;
+        }
+    "#;
+    assert_eq!(eval(sources), 352);
+}
+
+#[test]
+fn struct_test() {
+    let sources = r#"
+        struct Point { x: int, y: int }
+        struct Rect { min: Point, max: Point }
+        let origin = Point { y: 0, x: 0 };
+        fn area(r: Rect) -> int {
+            return (r.max.x - r.min.x) * (r.max.y - r.min.y);
+        }
+        fn shift(p: Point, d: int) -> Point {
+            return Point { x: p.x + d, y: p.y + d };
+        }
+        fn main() -> int {
+            let r = Rect { min: origin, max: Point { x: 2, y: 3 } };
+            r.max = shift(r.max, 1);
+            r.min.x = -1;
+            let p: Point;
+            p = shift(origin, 5);
+            return area(r) * 100 + shift(p, 1).y;
+        }
+    "#;
+    assert_eq!(eval(sources), 1606);
+}
+
+#[test]
+fn method_test() {
+    let sources = r#"
+        fn main() -> int {
+            let p = Point::new(1, 2);
+            p.scale(3);
+            let t = Point::new(1, 1).len2();
+            return p.len2() * 10 + t + p.twice();
+        }
+        impl Point {
+            fn new(x: int, y: int) -> Point { return Point { x: x, y: y }; }
+            fn len2(self) -> int { return self.x * self.x + self.y * self.y; }
+            fn scale(self, k: int) { self.x = self.x * k; self.y = self.y * k; }
+            fn twice(self) -> int { return self.sum() * 2; }
+            fn sum(self) -> int { return self.x + self.y; }
+        }
+        struct Point { x: int, y: int }
+    "#;
+    assert_eq!(eval(sources), 470);
+}
+
+#[test]
+fn enum_test() {
+    let sources = r#"
+        struct Point { x: int, y: int }
+        enum Shape { Circle(int), Rect(int, int), At(Point, int), Empty }
+        let none = Shape::Empty;
+        fn area(s: Shape) -> int {
+            return match s {
+                Shape::Circle(r) => 3 * r * r,
+                Shape::Rect(w, h) => w * h,
+                Shape::At(p, _) => p.x + p.y,
+                Shape::Empty => 0,
+            };
+        }
+        impl Shape {
+            fn is_empty(self) -> bool {
+                match self {
+                    Shape::Empty => { return true; }
+                    _ => { return false; }
+                }
+            }
+        }
+        fn main() -> int {
+            let total = area(Shape::Rect(2, 5)) + area(Shape::Circle(2));
+            total = total + area(Shape::At(Point { x: 100, y: 200 }, 7)) + area(none);
+            match Shape::Circle(1) {
+                Shape::Circle(r) => { total = total + r * 1000; }
+                other => { total = 0; }
+            }
+            if none.is_empty() { total = total + 10000; }
+            return total;
+        }
+    "#;
+    assert_eq!(eval(sources), 11322);
+
+    // `match` as an operand, and as an initializer that never yields
+    let sources = r#"
+        enum Op { Add(int), Done }
+        fn apply(n: int, op: Op) -> int {
+            let next = match op {
+                Op::Add(k) => { return n + k * match Op::Done { Op::Add(_) => 0, Op::Done => 10 }; }
+                Op::Done => { return n; }
+            };
+        }
+        fn main() -> int {
+            return apply(1, Op::Add(4)) * 100 + match Op::Add(3) { Op::Add(k) => k, _ => 0 } + apply(5, Op::Done);
+        }
+    "#;
+    assert_eq!(eval(sources), 4108);
+}
+
+#[test]
+fn interface_test() {
+    let sources = r#"
+        interface Shape {
+            fn area(self) -> int;
+            fn scale(self, k: int);
+        }
+        struct Square { side: int }
+        struct Rect { w: int, h: int }
+        impl Shape for Square {
+            fn area(self) -> int { return self.side * self.side; }
+            fn scale(self, k: int) { self.side = self.side * k; }
+        }
+        impl Shape for Rect {
+            fn area(self) -> int { return self.w * self.h; }
+            fn scale(self, k: int) { self.w = self.w * k; }
+        }
+        fn doubled<T: Shape>(s: T) -> int {
+            s.scale(2);
+            return s.area();
+        }
+        fn sum<A: Shape, B: Shape>(a: A, b: B) -> int {
+            return doubled(a) + b.area();
+        }
+        fn main() -> int {
+            let q = Square { side: 3 };
+            let r = Rect { w: 2, h: 5 };
+            return sum(q, r) * 1000 + doubled(r) + doubled(Square { side: 1 });
+        }
+    "#;
+    assert_eq!(eval(sources), 46024);
+}
+
+#[test]
+fn dyn_test() {
+    let sources = r#"
+        interface Shape {
+            fn area(self) -> int;
+            fn scale(self, k: int);
+        }
+        struct Square { side: int }
+        struct Rect { w: int, h: int }
+        impl Shape for Square {
+            fn area(self) -> int { return self.side * self.side; }
+            fn scale(self, k: int) { self.side = self.side * k; }
+        }
+        impl Shape for Rect {
+            fn area(self) -> int { return self.w * self.h; }
+            fn scale(self, k: int) { self.w = self.w * k; }
+        }
+        struct Pair { a: dyn Shape, b: dyn Shape }
+        fn grown(s: dyn Shape) -> int {
+            s.scale(2);
+            return s.area();
+        }
+        fn make(big: bool) -> dyn Shape {
+            if big { return Rect { w: 4, h: 5 }; }
+            return Square { side: 2 };
+        }
+        fn doubled<T: Shape>(s: T) -> int { return s.area() * 2; }
+        fn main() -> int {
+            let p = Pair { a: Square { side: 3 }, b: Rect { w: 2, h: 7 } };
+            let s: dyn Shape;
+            s = make(false);
+            return grown(p.a) * 1000 + p.b.area() * 10 + s.area() + doubled(make(true)) * 100000;
+        }
+    "#;
+    assert_eq!(eval(sources), 4036144);
+}
+
+#[test]
+fn dyn_box_test() {
+    // each pass builds a new box, `prev` keeps the one from the pass before
+    let sources = r#"
+        interface Val {
+            fn get(self) -> int;
+            fn bump(self);
+        }
+        struct C { n: int }
+        impl Val for C {
+            fn get(self) -> int { return self.n; }
+            fn bump(self) { self.n = self.n + 1; }
+        }
+        fn total(k: int) -> int {
+            let prev: dyn Val;
+            prev = C { n: 0 };
+            let cur: dyn Val;
+            cur = C { n: 0 };
+            let i = 0;
+            while i < k {
+                prev = cur;
+                cur = C { n: i * 10 };
+                cur.bump();
+                i = i + 1;
+            }
+            return prev.get() * 100 + cur.get();
+        }
+        fn main() -> int { return total(3); }
+    "#;
+    assert_eq!(eval(sources), 1121);
+}
+
+#[test]
+fn generic_test() {
+    let sources = r#"
+        struct Pair<T> { a: T, b: T }
+        struct Tagged<K, V> { key: K, val: Pair<V> }
+        fn max<T>(a: T, b: T) -> T {
+            if a > b { return a; }
+            return b;
+        }
+        fn larger<T>(p: Pair<T>) -> T { return max(p.a, p.b); }
+        fn swap<T>(p: Pair<T>) -> Pair<T> { return Pair { a: p.b, b: p.a }; }
+        fn nest<T>(x: T, y: T) -> Pair<Pair<T>> {
+            let p = Pair { a: x, b: y };
+            return Pair { a: p, b: swap(p) };
+        }
+        fn sum(t: Tagged<int, int>) -> int { return t.key + t.val.a + t.val.b; }
+        fn main() -> int {
+            let p = Pair { a: 3, b: 9 };
+            let q: Pair<float>;
+            q = Pair { a: 2.5, b: 1.5 };
+            let t = Tagged { key: 100, val: swap(p) };
+            if max(q.a, q.b) > 2.0 {
+                return nest(1, 2).b.a * 10000 + max(4, 7) * 1000 + larger(p) * 100 + sum(t) + swap(p).a - max(-3, -5);
+            }
+            return 0;
+        }
+    "#;
+    assert_eq!(eval(sources), 28024);
+
+    // the expected type gives the type arguments of a literal
+    let sources = r#"
+        interface A { fn get(self) -> int; }
+        struct S { x: int }
+        impl A for S {
+            fn get(self) -> int { return self.x; }
+        }
+        struct Box<T> { v: T }
+        fn unbox(b: Box<dyn A>) -> int { return b.v.get(); }
+        fn make() -> Box<dyn A> { return Box { v: S { x: 4 } }; }
+        fn main() -> int {
+            let b: Box<dyn A>;
+            b = Box { v: S { x: 3 } };
+            return unbox(b) * 10 + make().v.get();
+        }
+    "#;
+    assert_eq!(eval(sources), 34);
+}
@@ -1,17 +1,57 @@
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use crate::ast::AstType;
+use crate::ast::Generics;
 use crate::ast::AstNode;
 use crate::ast::ident_name;
+use crate::ast::LoopLabel;
+use crate::error::{Diagnostic, E_REPORTED};
+use crate::span::Span;
 use std::fmt;
 
 type DefTable = HashMap<String, AstType>;
 type Scope = Vec<DefTable>;
 
+/// Names resolve from the innermost scope outwards. Function parameters and the
+/// function body share one scope, and every nested `{ }` block opens another.
+/// A `let` may shadow a name from an enclosing scope, but defining the same name
+/// twice in one scope is an error.
 #[derive(Debug)]
 pub struct Env {
     pub global: DefTable,
     pub locals: Scope,
+    pub diagnostics: Vec<Diagnostic>,
+    // labels of the loops enclosing the statement being checked
+    pub loops: Vec<LoopLabel>,
+    // fields of every struct, in declaration order
+    pub structs: HashMap<String, Vec<(String, AstType)>>,
+    // variants of every enum with their payload types, the index is the tag
+    pub enums: HashMap<String, Vec<(String, Vec<AstType>)>>,
+    // methods of every interface: name, parameter types after `self`, return type
+    pub interfaces: HashMap<String, Vec<(String, Vec<AstType>, AstType)>>,
+    // (type, interface) of every `impl Interface for Type`
+    pub impls: HashSet<(String, String)>,
+    // generic functions as declared, instantiated per call
+    pub generics: HashMap<String, Vec<AstNode>>,
+    // type parameters of the generic function being checked
+    pub type_params: Generics,
+    // type parameters of every generic struct, its fields in `structs` are written with them
+    pub struct_generics: HashMap<String, Generics>,
+    // instances of generic functions and structs still to be checked, with the
+    // use that required them and how many instances led to it
+    pub instances: Vec<(AstNode, Span, usize)>,
+    // depth of the instance being checked, 0 outside of instances
+    pub instance_depth: usize,
+    // overload key of every instance -> return type and symbol
+    pub instantiated: HashMap<String, (AstType, String)>,
+    // folded value of every `const`
+    pub consts: HashMap<String, AstNode>,
+    // parameter types of every overload of a function
+    pub overloads: HashMap<String, Vec<Vec<AstType>>>,
+    // overload key (see `semantic::prototype_fn`) -> symbol in the LLVM module
+    pub symbols: HashMap<String, String>,
+    // declared return type of the function being checked and where it is written
+    pub fn_ret: Option<(AstType, Span)>,
 }
 
 impl fmt::Display for Env {
@@ -25,6 +65,22 @@ impl Env {
         Env {
             global: HashMap::new(),
             locals: Vec::new(),
+            diagnostics: Vec::new(),
+            loops: Vec::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            interfaces: HashMap::new(),
+            impls: HashSet::new(),
+            generics: HashMap::new(),
+            type_params: Vec::new(),
+            struct_generics: HashMap::new(),
+            instances: Vec::new(),
+            instance_depth: 0,
+            instantiated: HashMap::new(),
+            consts: HashMap::new(),
+            overloads: HashMap::new(),
+            symbols: HashMap::new(),
+            fn_ret: None,
         }
     }
 
@@ -51,7 +107,7 @@ impl Env {
         }
     }
 
-    pub fn _local_defined(&mut self, var: &String) -> bool {
+    pub fn local_defined(&mut self, var: &String) -> bool {
         let mut ok = false;
         if let Some(top) = self.locals.pop() {
             ok = top.contains_key(var);
@@ -77,7 +133,7 @@ impl Env {
     pub fn update(&mut self, var: &mut AstNode, typ: AstType) {
         let len = self.locals.len();
         let name = ident_name(var);
-        if let AstNode::Ident(_, ref mut vtyp) = var {
+        if let AstNode::Ident(_, ref mut vtyp, _) = var {
             *vtyp = typ.clone();
         }
         for idx in 0..len {
@@ -89,11 +145,15 @@ impl Env {
         self.global.entry(name).and_modify(|e| { *e = typ });
     }
 
-    pub fn can_lookup(&self, var: &String) -> bool {
-        match self.lookup(var) {
-            Some(_) => true,
-            _ => false,
-        }
+    // value of the constant `var` refers to, unless a local shadows it
+    pub fn const_value(&self, var: &String) -> Option<&AstNode> {
+        if self.locals.iter().any(|s| s.contains_key(var)) { return None; }
+        self.consts.get(var)
+    }
+
+    pub fn report(&mut self, diag: Diagnostic) {
+        if diag.code == E_REPORTED { return; }
+        self.diagnostics.push(diag);
     }
 
 }
@@ -0,0 +1,284 @@
+use std::fmt;
+use lalrpop_util::ParseError;
+use crate::span::{FileId, SourceMap, Span};
+
+// error codes reported by the compiler
+pub const E_REDEFINED: &str = "E0001";
+pub const E_UNRESOLVED_NAME: &str = "E0002";
+pub const E_UNRESOLVED_FN: &str = "E0003";
+pub const E_MISMATCHED_TYPES: &str = "E0004";
+pub const E_SYNTAX: &str = "E0005";
+pub const E_OUTSIDE_LOOP: &str = "E0006";
+pub const E_UNDECLARED_LABEL: &str = "E0007";
+pub const E_MISSING_RETURN: &str = "E0008";
+pub const E_UNINITIALIZED: &str = "E0009";
+pub const E_AMBIGUOUS: &str = "E0010";
+pub const E_NOT_CONST: &str = "E0011";
+pub const E_ASSIGN_CONST: &str = "E0012";
+pub const E_INVALID_ASSIGN: &str = "E0013";
+pub const E_NO_FIELD: &str = "E0014";
+pub const E_UNKNOWN_TYPE: &str = "E0015";
+pub const E_INVALID_SELF: &str = "E0016";
+pub const E_NON_EXHAUSTIVE: &str = "E0017";
+pub const E_UNSATISFIED_BOUND: &str = "E0018";
+pub const E_INTERFACE_IMPL: &str = "E0019";
+pub const E_CANNOT_INFER: &str = "E0020";
+pub const E_RECURSION_LIMIT: &str = "E0021";
+pub const E_INFINITE_SIZE: &str = "E0022";
+pub const E_CYCLE: &str = "E0023";
+pub const E_RANGE_STEP: &str = "E0024";
+pub const E_LITERAL_RANGE: &str = "E0025";
+
+// an error that follows from one already reported, it is not shown
+pub const E_REPORTED: &str = "E0000";
+
+// warning codes
+pub const W_UNREACHABLE: &str = "W0001";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelStyle {
+    Primary,
+    Secondary,
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub style: LabelStyle,
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Label {
+    pub fn primary(span: Span, message: impl Into<String>) -> Self {
+        Label { style: LabelStyle::Primary, span, message: message.into() }
+    }
+
+    pub fn secondary(span: Span, message: impl Into<String>) -> Self {
+        Label { style: LabelStyle::Secondary, span, message: message.into() }
+    }
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Warning, code, message)
+    }
+
+    // aborts checking an expression without reporting anything new
+    pub fn reported() -> Self {
+        Diagnostic::new(Severity::Error, E_REPORTED, "")
+    }
+
+    pub fn with_label(mut self, label: Label) -> Self {
+        self.labels.push(label);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+pub fn has_errors(diags: &[Diagnostic]) -> bool {
+    diags.iter().any(|d| d.is_error())
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        s.fmt(f)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)?;
+        for label in &self.labels {
+            let mark = match label.style {
+                LabelStyle::Primary => '^',
+                LabelStyle::Secondary => '-',
+            };
+            write!(f, "\n  --> {} {} {}", label.span, mark, label.message)?;
+        }
+        for note in &self.notes {
+            write!(f, "\n  = note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+/// Converts a lalrpop parse error on `file`, whose text is `source`, into a diagnostic.
+pub fn parse_error<T: fmt::Display>(file: FileId, source: &str, err: ParseError<usize, T, (usize, usize, &str)>) -> Diagnostic {
+    match err {
+        ParseError::InvalidToken { location } => {
+            Diagnostic::error(E_SYNTAX, "invalid token")
+                .with_label(Label::primary(Span::new(file, location, location + 1), "unrecognized character"))
+        }
+        ParseError::UnrecognizedToken { token: Some((s, tok, e)), expected } => {
+            Diagnostic::error(E_SYNTAX, format!("expected {}, found `{}`", expected_tokens(&expected), tok))
+                .with_label(Label::primary(Span::new(file, s, e), "unexpected token"))
+        }
+        ParseError::UnrecognizedToken { token: None, expected } => {
+            // lalrpop reports no location for a premature end of input
+            let eof = Span::new(file, source.len(), source.len());
+            Diagnostic::error(E_SYNTAX, format!("expected {}, found end of file", expected_tokens(&expected)))
+                .with_label(Label::primary(eof, "unexpected end of file"))
+        }
+        ParseError::ExtraToken { token: (s, tok, e) } => {
+            Diagnostic::error(E_SYNTAX, format!("unexpected token `{}`", tok))
+                .with_label(Label::primary(Span::new(file, s, e), "expected end of file"))
+        }
+        ParseError::User { error: (s, e, msg) } => {
+            Diagnostic::error(E_SYNTAX, msg)
+                .with_label(Label::primary(Span::new(file, s, e), msg))
+        }
+    }
+}
+
+fn expected_tokens(expected: &[String]) -> String {
+    let names: Vec<String> = expected.iter().map(|t| describe_token(t)).collect();
+    match names.len() {
+        0 => "nothing".to_string(),
+        1 => names[0].clone(),
+        _ => format!("one of {}", names.join(", ")),
+    }
+}
+
+/// Turns the terminal names lalrpop reports (`"\"fn\""`, regex literals) into
+/// something readable.
+fn describe_token(token: &str) -> String {
+    if token.starts_with("r#") {
+        let name = match token {
+            r##"r#"[A-Za-z_][A-Za-z_0-9]*"#"## => "identifier",
+            r##"r#"[0-9]+"#"## => "integer",
+            r##"r#"[0-9]+\\.[0-9]+"#"## => "float",
+            r##"r#"'[A-Za-z_][A-Za-z_0-9]*"#"## => "loop label",
+            _ => "string",
+        };
+        return name.to_string();
+    }
+    format!("`{}`", token.trim_matches('"'))
+}
+
+/// Renders `diag` rustc style, quoting the source lines its labels point at.
+pub fn render(diag: &Diagnostic, sources: &SourceMap) -> String {
+    let mut out = format!("{}[{}]: {}\n", diag.severity, diag.code, diag.message);
+
+    let mut labels: Vec<&Label> = diag.labels.iter().collect();
+    labels.sort_by_key(|l| (l.style != LabelStyle::Primary, l.span.start));
+    let width = labels.iter()
+        .map(|l| sources.get(l.span.file).line_col(l.span.start).0.to_string().len())
+        .max()
+        .unwrap_or(0);
+    let gutter = " ".repeat(width);
+
+    if let Some(first) = labels.first() {
+        let file = sources.get(first.span.file);
+        let (line, col) = file.line_col(first.span.start);
+        out.push_str(&format!("{}--> {}:{}:{}\n", gutter, file.name, line, col));
+    }
+
+    // one snippet per source line, with every label on that line beneath it
+    labels.sort_by_key(|l| (l.span.file.0, sources.get(l.span.file).line_index(l.span.start), l.style != LabelStyle::Primary));
+    let mut idx = 0;
+    while idx < labels.len() {
+        let file_id = labels[idx].span.file;
+        let file = sources.get(file_id);
+        let line = file.line_index(labels[idx].span.start);
+        let text = file.line(line);
+        out.push_str(&format!("{} |\n", gutter));
+        out.push_str(&format!("{:>w$} | {}\n", line + 1, text, w = width));
+        while idx < labels.len() && labels[idx].span.file == file_id
+            && file.line_index(labels[idx].span.start) == line {
+            let label = labels[idx];
+            let (_, col) = file.line_col(label.span.start);
+            // spans running past the end of the line are cut at the line break
+            let end_col = if label.span.end > label.span.start && file.line_index(label.span.end - 1) == line {
+                file.line_col(label.span.end).1
+            } else {
+                text.chars().count() + 1
+            };
+            let mark = match label.style {
+                LabelStyle::Primary => "^",
+                LabelStyle::Secondary => "-",
+            };
+            out.push_str(&format!("{} | {}{} {}\n", gutter, " ".repeat(col - 1),
+                mark.repeat(end_col.saturating_sub(col).max(1)), label.message));
+            idx += 1;
+        }
+    }
+    for note in &diag.notes {
+        out.push_str(&format!("{} = note: {}\n", gutter, note));
+    }
+    out
+}
+
+#[test]
+fn render_test() {
+    let mut sources = SourceMap::new();
+    let file = sources.add("demo.ds".to_string(), "fn main() {\n    let a = 1 + 2.5;\n}\n".to_string());
+    let diag = Diagnostic::error(E_MISMATCHED_TYPES, "mismatched types")
+        .with_label(Label::primary(Span::new(file, 28, 31), "expected int, found float"))
+        .with_label(Label::secondary(Span::new(file, 24, 25), "this is int"));
+    let expected = "\
+error[E0004]: mismatched types
+ --> demo.ds:2:17
+  |
+2 |     let a = 1 + 2.5;
+  |                 ^^^ expected int, found float
+  |             - this is int
+";
+    assert_eq!(render(&diag, &sources), expected);
+}
+
+#[test]
+fn eof_error_test() {
+    use crate::grammar::ModuleParser;
+    let src = "fn main() {\n    let a = 1;";
+    let mut sources = SourceMap::new();
+    let file = sources.add("demo.ds".to_string(), src.to_string());
+    let err = ModuleParser::new().parse(file, src).unwrap_err();
+    let rendered = render(&parse_error(file, src, err), &sources);
+    assert!(rendered.ends_with("\
+ --> demo.ds:2:15
+  |
+2 |     let a = 1;
+  |               ^ unexpected end of file
+"), "{}", rendered);
+}
@@ -1,5 +1,7 @@
 
 mod ast;
+mod span;
+mod error;
 mod env;
 mod semantic;
 mod codegen;
@@ -13,6 +15,8 @@ lalrpop_mod!(pub grammar);
 extern crate clap;
 use clap::{Arg, App};
 
+use crate::error::{has_errors, parse_error, render};
+use crate::span::SourceMap;
 use crate::semantic::semantic_check;
 use crate::grammar::ModuleParser;
 use crate::codegen::LLVMGenerator;
@@ -27,8 +31,22 @@ fn main() {
 
     let contents = std::fs::read_to_string(fname)
         .expect("[error] read_to_string");
-    let stmts = ModuleParser::new().parse(&contents.to_string()).unwrap();
-    let typed_ast = semantic_check(stmts);
+    let mut sources = SourceMap::new();
+    let file = sources.add(fname.to_string(), contents.clone());
+    let stmts = match ModuleParser::new().parse(file, &contents) {
+        Ok(stmts) => stmts,
+        Err(err) => {
+            eprint!("{}", render(&parse_error(file, &contents, err), &sources));
+            std::process::exit(1);
+        }
+    };
+    let (typed_ast, diags) = semantic_check(stmts);
+    for diag in &diags {
+        eprint!("{}", render(diag, &sources));
+    }
+    if has_errors(&diags) {
+        std::process::exit(1);
+    }
     unsafe {
 
     let mut generator = LLVMGenerator::new();
@@ -1,197 +1,2597 @@
 use crate::ast::*;
 use crate::env::*;
+use crate::error::*;
+use std::collections::HashMap;
 
-pub fn semantic_check(stmt: Vec<AstNode>) -> Vec<AstNode> {
+type CheckResult<T> = Result<T, Diagnostic>;
+
+// instances a chain of generic calls may create, it only grows without bound
+// when a function calls itself with ever larger types
+const INSTANCE_DEPTH_LIMIT: usize = 64;
+
+pub fn semantic_check(stmt: Vec<AstNode>) -> (Vec<AstNode>, Vec<Diagnostic>) {
     let mut ev = Env::new();
     let mut _stmt = stmt.clone();
-    for (_, e) in _stmt.iter_mut().enumerate() {
-        match e {
-            AstNode::FnDecl(_, _, _) => check_fndecl(&mut ev, e),
-            AstNode::VarDecl(_, _, _) => check_vardecl(&mut ev, e, true),
-            _ => (),
+    // every item is declared before any body is checked, so declaration order
+    // does not matter: structs, then function prototypes, then globals and
+    // constants (each after the ones its initializer names, as initializers
+    // are folded)
+    for pass in 0..4 {
+        let order = if pass == 2 { global_order(&mut ev, &_stmt) } else { (0.._stmt.len()).collect() };
+        for i in order {
+            let e = &mut _stmt[i];
+            let res = match (pass, &e) {
+                (0, AstNode::StructDecl(_, _, _, _)) => declare_struct(&mut ev, e),
+                (0, AstNode::EnumDecl(_, _, _)) => declare_enum(&mut ev, e),
+                (0, AstNode::InterfaceDecl(_, _, _)) => declare_interface(&mut ev, e),
+                (0, AstNode::ImplDecl(_, _, _, _)) => qualify_methods(e),
+                (1, AstNode::StructDecl(_, _, _, _)) => check_struct(&mut ev, e),
+                (1, AstNode::EnumDecl(_, _, _)) => check_enum(&mut ev, e),
+                (1, AstNode::InterfaceDecl(_, _, _)) => check_interface(&mut ev, e),
+                (1, AstNode::FnDecl(_, _, _, _, _, _, _)) => declare_fn(&mut ev, e),
+                (1, AstNode::ImplDecl(_, _, _, _)) => declare_impl(&mut ev, e),
+                (2, AstNode::VarDecl(_, _, _, _)) => check_vardecl(&mut ev, e, true),
+                (2, AstNode::ConstDecl(_, _, _, _)) => check_constdecl(&mut ev, e),
+                (3, AstNode::FnDecl(_, _, _, _, _, _, _)) => check_fndecl(&mut ev, e),
+                (3, AstNode::ImplDecl(_, _, _, _)) => check_impl(&mut ev, e),
+                _ => Ok(()),
+            };
+            if let Err(diag) = res { ev.report(diag); }
+        }
+    }
+    // generic bodies were checked against their bounds; instances are only
+    // checked (and may instantiate more) once the module is known to be sound
+    if !has_errors(&ev.diagnostics) {
+        while let Some((mut inst, site, depth)) = ev.instances.pop() {
+            ev.instance_depth = depth;
+            let before = ev.diagnostics.len();
+            if let Err(diag) = check_fndecl(&mut ev, &mut inst) { ev.report(diag); }
+            // warnings were reported on the generic body already, errors point
+            // back at the use that required the instance
+            let mut diags = ev.diagnostics.split_off(before);
+            diags.retain(|d| d.severity == Severity::Error);
+            for diag in diags {
+                ev.report(diag.with_label(Label::secondary(site, "required by this instantiation")));
+            }
+            _stmt.push(inst);
         }
     }
-    _stmt
+    (_stmt, ev.diagnostics)
 }
 
-fn prototype_fn(ev: &mut Env, ident: String, p: &mut Vec<AstNode>) -> String {
-    ident + &join_param(ev, p)
+// Indices of the globals and constants in `stmts`, each after the ones its
+// initializer names. The members of a cycle are reported and left out.
+fn global_order(ev: &mut Env, stmts: &[AstNode]) -> Vec<usize> {
+    let mut globals: HashMap<String, usize> = HashMap::new();
+    for (i, n) in stmts.iter().enumerate() {
+        if let AstNode::VarDecl(var, _, _, _) | AstNode::ConstDecl(var, _, _, _) = n {
+            globals.entry(ident_name(var)).or_insert(i);
+        }
+    }
+    let mut done = vec![false; stmts.len()];
+    let mut order = Vec::new();
+    let mut cycles = Vec::new();
+    let mut starts: Vec<usize> = globals.values().cloned().collect();
+    starts.sort();
+    for i in starts {
+        visit_global(stmts, &globals, i, &mut Vec::new(), &mut done, &mut order, &mut cycles);
+    }
+    let mut cyclic: Vec<usize> = Vec::new();
+    for cycle in cycles {
+        if cycle.iter().any(|i| cyclic.contains(i)) { continue; }
+        ev.report(global_cycle(stmts, &cycle));
+        cyclic.extend(cycle);
+    }
+    for i in cyclic.iter() {
+        if let AstNode::VarDecl(var, _, _, _) | AstNode::ConstDecl(var, _, _, _) = &stmts[*i] {
+            ev.global_def(&ident_name(var), AstType::Undef);
+        }
+    }
+    order.retain(|i| !cyclic.contains(i));
+    order
 }
 
-fn check_fndecl(ev: &mut Env, n: &mut AstNode) {
-    if let AstNode::FnDecl(ident, ref mut param, block) = n {
-        let proto = prototype_fn(ev, ident_name(&ident), param);
-        if ev.global_defined(&proto) { unreachable!("redefine function:{}", proto) }
-        if let AstNode::Ident(_, typ) = *ident.clone() {
-            ev.global_def(&proto, typ);
-            ev.enter_scope();
-            define_local_var(ev, &param);
+fn visit_global(stmts: &[AstNode], globals: &HashMap<String, usize>, i: usize, path: &mut Vec<usize>,
+                done: &mut Vec<bool>, order: &mut Vec<usize>, cycles: &mut Vec<Vec<usize>>) {
+    if done[i] { return; }
+    if let Some(pos) = path.iter().position(|j| *j == i) {
+        cycles.push(path[pos..].to_vec());
+        return;
+    }
+    let mut names = Vec::new();
+    if let AstNode::VarDecl(_, val, _, _) | AstNode::ConstDecl(_, val, _, _) = &stmts[i] {
+        names_in(val, &mut names);
+    }
+    path.push(i);
+    for name in names {
+        if let Some(j) = globals.get(&name) {
+            visit_global(stmts, globals, *j, path, done, order, cycles);
+        }
+    }
+    path.pop();
+    done[i] = true;
+    order.push(i);
+}
+
+// names an initializer reads; others are not constant anyway
+fn names_in(n: &AstNode, names: &mut Vec<String>) {
+    match n {
+        AstNode::Ident(name, _, _) => names.push(name.clone()),
+        AstNode::UnaryOp(_, val, _, _) | AstNode::FieldAccess(val, _, _, _, _) => names_in(val, names),
+        AstNode::BinaryOp(lhs, _, rhs, _, _) => { names_in(lhs, names); names_in(rhs, names); }
+        AstNode::StructLit(_, fields, _) => fields.iter().for_each(|(_, val)| names_in(val, names)),
+        AstNode::FnCall(_, args, _, _) | AstNode::EnumLit(_, _, args, _, _) => args.iter().for_each(|arg| names_in(arg, names)),
+        _ => (),
+    }
+}
+
+fn global_cycle(stmts: &[AstNode], cycle: &[usize]) -> Diagnostic {
+    let name = |i: usize| match &stmts[i] {
+        AstNode::VarDecl(var, _, _, _) | AstNode::ConstDecl(var, _, _, _) => (ident_name(var), var.span()),
+        _ => unreachable!(),
+    };
+    let (first, _) = name(cycle[0]);
+    let mut diag = Diagnostic::error(E_CYCLE, format!("cycle detected when checking the initializer of `{}`", first));
+    for (k, i) in cycle.iter().enumerate() {
+        let (_, span) = name(*i);
+        let (next, _) = name(cycle[(k + 1) % cycle.len()]);
+        diag = diag.with_label(if k == 0 {
+            Label::primary(span, format!("its initializer needs `{}`", next))
+        } else {
+            Label::secondary(span, format!("which needs `{}`", next))
+        });
+    }
+    diag.with_note("a global or constant cannot be initialized from itself")
+}
+
+fn declare_struct(ev: &mut Env, n: &mut AstNode) -> CheckResult<()> {
+    if let AstNode::StructDecl(ident, generics, fields, _) = n {
+        let name = ident_name(ident);
+        if ev.structs.contains_key(&name) || ev.enums.contains_key(&name) {
+            return Err(Diagnostic::error(E_REDEFINED, format!("struct `{}` is defined multiple times", name))
+                .with_label(Label::primary(ident.span(), format!("`{}` redefined here", name))));
+        }
+        let fields = fields.iter().map(|f| (ident_name(f), ident_type(f))).collect();
+        if !generics.is_empty() { ev.struct_generics.insert(name.clone(), generics.clone()); }
+        ev.structs.insert(name, fields);
+    }
+    Ok(())
+}
+
+fn declare_interface(ev: &mut Env, n: &mut AstNode) -> CheckResult<()> {
+    if let AstNode::InterfaceDecl(ident, sigs, _) = n {
+        let name = ident_name(ident);
+        if ev.structs.contains_key(&name) || ev.enums.contains_key(&name) || ev.interfaces.contains_key(&name) {
+            return Err(Diagnostic::error(E_REDEFINED, format!("interface `{}` is defined multiple times", name))
+                .with_label(Label::primary(ident.span(), format!("`{}` redefined here", name))));
+        }
+        let mut decl: Vec<(String, Vec<AstType>, AstType)> = Vec::new();
+        for sig in sigs.iter() {
+            if let AstNode::FnDecl(fident, _, param, _, _, _, _) = sig {
+                let mname = ident_name(fident);
+                match param.first() {
+                    Some(AstNode::Ident(_, AstType::Ref(_), _)) => (),
+                    _ => {
+                        return Err(Diagnostic::error(E_INTERFACE_IMPL, format!("interface method `{}` must take `self`", mname))
+                            .with_label(Label::primary(fident.span(), "missing `self` parameter")));
+                    }
+                }
+                if decl.iter().any(|m| m.0 == mname) {
+                    return Err(Diagnostic::error(E_REDEFINED, format!("method `{}` is declared multiple times in interface `{}`", mname, name))
+                        .with_label(Label::primary(fident.span(), format!("`{}` redeclared here", mname))));
+                }
+                decl.push((mname, param[1..].iter().map(ident_type).collect(), ident_type(fident)));
+            }
+        }
+        ev.interfaces.insert(name, decl);
+    }
+    Ok(())
+}
+
+fn check_interface(ev: &mut Env, n: &mut AstNode) -> CheckResult<()> {
+    if let AstNode::InterfaceDecl(_, sigs, _) = n {
+        for sig in sigs.iter() {
+            if let AstNode::FnDecl(fident, _, param, rspan, _, _, _) = sig {
+                for p in param.iter().skip(1) {
+                    check_type(ev, &ident_type(p), p.span())?;
+                }
+                check_type(ev, &ident_type(fident), *rspan)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn declare_enum(ev: &mut Env, n: &mut AstNode) -> CheckResult<()> {
+    if let AstNode::EnumDecl(ident, variants, _) = n {
+        let name = ident_name(ident);
+        if ev.structs.contains_key(&name) || ev.enums.contains_key(&name) {
+            return Err(Diagnostic::error(E_REDEFINED, format!("enum `{}` is defined multiple times", name))
+                .with_label(Label::primary(ident.span(), format!("`{}` redefined here", name))));
+        }
+        let mut decl: Vec<(String, Vec<AstType>)> = Vec::new();
+        for (vident, typs) in variants.iter() {
+            let vname = ident_name(vident);
+            if decl.iter().any(|v| v.0 == vname) {
+                return Err(Diagnostic::error(E_REDEFINED, format!("variant `{}::{}` is defined multiple times", name, vname))
+                    .with_label(Label::primary(vident.span(), format!("`{}` redefined here", vname))));
+            }
+            decl.push((vname, typs.iter().map(|t| t.0.clone()).collect()));
+        }
+        ev.enums.insert(name, decl);
+    }
+    Ok(())
+}
+
+fn check_enum(ev: &mut Env, n: &mut AstNode) -> CheckResult<()> {
+    if let AstNode::EnumDecl(ident, variants, _) = n {
+        let name = ident_name(ident);
+        if let Some(v) = variants.iter().find(|v| v.1.iter().any(|t| contains_type(ev, &t.0, &name, type_depth(ev)))) {
+            return Err(infinite_size(&name, v.0.span()));
+        }
+        for (_, typs) in variants.iter() {
+            for (typ, span) in typs {
+                check_type(ev, typ, *span)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// field types may name structs declared anywhere in the module
+fn check_struct(ev: &mut Env, n: &mut AstNode) -> CheckResult<()> {
+    if let AstNode::StructDecl(ident, generics, fields, _) = n {
+        check_bounds_exist(ev, generics, ident.span())?;
+        let name = ident_name(ident);
+        if let Some(field) = fields.iter().find(|f| contains_type(ev, &ident_type(f), &name, type_depth(ev))) {
+            return Err(infinite_size(&name, field.span()));
+        }
+        ev.type_params = generics.clone();
+        let res = fields.iter().try_for_each(|field| check_type(ev, &ident_type(field), field.span()));
+        ev.type_params.clear();
+        res?;
+    }
+    Ok(())
+}
+
+// a type written in the source must be built in or a declared struct
+fn check_type(ev: &mut Env, typ: &AstType, span: Span) -> CheckResult<()> {
+    match typ {
+        AstType::Generic(base, args) => check_instance(ev, typ, base, args, span),
+        AstType::Ext(name) if ev.struct_generics.contains_key(name) => {
+            Err(Diagnostic::error(E_CANNOT_INFER, format!("missing type arguments for generic struct `{}`", name))
+                .with_label(Label::primary(span, format!("expected `{}`", render_generic(name, &ev.struct_generics[name])))))
+        }
+        AstType::Ext(name) if !ev.structs.contains_key(name) && !ev.enums.contains_key(name) &&
+                              !ev.type_params.iter().any(|p| p.0 == *name) => {
+            Err(Diagnostic::error(E_UNKNOWN_TYPE, format!("unknown type `{}`", name))
+                .with_label(Label::primary(span, "not a built-in type, a struct or an enum in this module")))
+        }
+        // a receiver not typed by `qualify_methods`
+        AstType::Ref(inner) if **inner == AstType::Undef => {
+            Err(Diagnostic::error(E_INVALID_SELF, "`self` parameter is only allowed in an `impl` block")
+                .with_label(Label::primary(span, "not a method")))
+        }
+        AstType::Ref(inner) => check_type(ev, inner, span),
+        AstType::Dyn(iface) if !ev.interfaces.contains_key(iface) => {
+            Err(Diagnostic::error(E_UNKNOWN_TYPE, format!("cannot find interface `{}`", iface))
+                .with_label(Label::primary(span, "not an interface in this module")))
+        }
+        _ => Ok(()),
+    }
+}
+
+// `Pair<int>` instantiates the generic struct `Pair` on first use; instances
+// are keyed by their type as written
+fn check_instance(ev: &mut Env, typ: &AstType, base: &str, args: &[AstType], span: Span) -> CheckResult<()> {
+    let generics = match ev.struct_generics.get(base) {
+        Some(generics) => generics.clone(),
+        None => {
+            return Err(Diagnostic::error(E_UNKNOWN_TYPE, format!("unknown generic struct `{}`", base))
+                .with_label(Label::primary(span, "not a generic struct in this module")));
+        }
+    };
+    if args.len() != generics.len() {
+        return Err(Diagnostic::error(E_MISMATCHED_TYPES, format!("struct `{}` takes {} type argument(s) but {} were supplied", base, generics.len(), args.len()))
+            .with_label(Label::primary(span, format!("expected `{}`", render_generic(base, &generics)))));
+    }
+    for ((tp, bound), arg) in generics.iter().zip(args.iter()) {
+        check_type(ev, arg, span)?;
+        if let Some(iface) = bound {
+            if !implements(ev, arg, iface) {
+                return Err(Diagnostic::error(E_UNSATISFIED_BOUND, format!("the bound `{}: {}` is not satisfied", tp, iface))
+                    .with_label(Label::primary(span, format!("`{}` does not implement `{}`", arg, iface)))
+                    .with_note(format!("required by a bound on `{}`", base)));
+            }
+        }
+    }
+    let name = typ.to_string();
+    if ev.structs.contains_key(&name) { return Ok(()); }
+    let map: HashMap<String, AstType> = generics.iter().map(|g| g.0.clone()).zip(args.iter().cloned()).collect();
+    let mut fields = ev.structs[base].clone();
+    fields.iter_mut().for_each(|f| subst_type(&mut f.1, &map));
+    ev.structs.insert(name.clone(), fields.clone());
+    // reported on the declaration, and its field types would never end
+    if ev.structs[base].iter().any(|f| contains_type(ev, &f.1, base, type_depth(ev))) {
+        return Ok(());
+    }
+    for f in fields.iter() {
+        check_type(ev, &f.1, span)?;
+    }
+    // `Pair<T>` in a generic body only types its fields
+    if args.iter().any(|t| ev.type_params.iter().any(|p| mentions_type(t, &p.0))) {
+        return Ok(());
+    }
+    let ident = AstNode::Ident(name, typ.clone(), span);
+    let fields = fields.into_iter().map(|(f, t)| AstNode::Ident(f, t, span)).collect();
+    ev.instances.push((AstNode::StructDecl(Box::new(ident), Vec::new(), fields, span), span, ev.instance_depth));
+    Ok(())
+}
+
+// whether a value of `typ` holds a value of the struct or enum `name` in
+// place, following fields and payloads at most `depth` levels deep
+fn contains_type(ev: &Env, typ: &AstType, name: &str, depth: usize) -> bool {
+    let (base, args) = match typ {
+        AstType::Ext(base) => (base, &[][..]),
+        AstType::Generic(base, args) => (base, &args[..]),
+        _ => return false,
+    };
+    if base == name { return true; }
+    if depth == 0 { return false; }
+    let map: HashMap<String, AstType> = ev.struct_generics.get(base).into_iter().flatten()
+        .map(|g| g.0.clone()).zip(args.iter().cloned()).collect();
+    let payloads = ev.enums.get(base).into_iter().flatten().flat_map(|v| v.1.iter());
+    ev.structs.get(base).into_iter().flatten().map(|f| &f.1).chain(payloads).any(|t| {
+        let mut t = t.clone();
+        subst_type(&mut t, &map);
+        contains_type(ev, &t, name, depth - 1)
+    })
+}
+
+// a cycle of types passes through each declaration at most once
+fn type_depth(ev: &Env) -> usize {
+    ev.structs.len() + ev.enums.len()
+}
+
+fn infinite_size(name: &str, span: Span) -> Diagnostic {
+    Diagnostic::error(E_INFINITE_SIZE, format!("recursive type `{}` has infinite size", name))
+        .with_label(Label::primary(span, format!("contains a `{}` in place", name)))
+        .with_note(format!("a value of `{}` would contain itself", name))
+}
+
+fn render_generic(name: &str, generics: &Generics) -> String {
+    let tps: Vec<String> = generics.iter().map(|(tp, bound)| match bound {
+        Some(iface) => format!("{}: {}", tp, iface),
+        None => tp.clone(),
+    }).collect();
+    format!("{}<{}>", name, tps.join(", "))
+}
+
+// `fn len(self)` in `impl Point` becomes `fn Point::len(self: &Point)`
+fn qualify_methods(n: &mut AstNode) -> CheckResult<()> {
+    if let AstNode::ImplDecl(ident, _, fns, _) = n {
+        let tname = ident_name(ident);
+        for f in fns.iter_mut() {
+            if let AstNode::FnDecl(fident, _, ref mut param, _, _, _, _) = f {
+                if let AstNode::Ident(ref mut name, _, _) = **fident {
+                    *name = format!("{}::{}", tname, name);
+                }
+                if let Some(AstNode::Ident(_, ref mut typ, _)) = param.first_mut() {
+                    if *typ == AstType::Ref(Box::new(AstType::Undef)) {
+                        *typ = AstType::Ref(Box::new(AstType::Ext(tname.clone())));
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn declare_impl(ev: &mut Env, n: &mut AstNode) -> CheckResult<()> {
+    if let AstNode::ImplDecl(ident, iface, fns, _) = n {
+        check_type(ev, &AstType::Ext(ident_name(ident)), ident.span())?;
+        for f in fns.iter_mut() {
+            if let Err(diag) = declare_fn(ev, f) { ev.report(diag); }
+        }
+        if let Some(iface) = iface {
+            check_conformance(ev, &ident_name(ident), iface, fns)?;
+        }
+    }
+    Ok(())
+}
+
+// an `impl Interface for Type` has exactly the interface's methods, with the same signatures
+fn check_conformance(ev: &mut Env, tname: &str, iface: &AstNode, fns: &Vec<AstNode>) -> CheckResult<()> {
+    let iname = ident_name(iface);
+    let sigs = match ev.interfaces.get(&iname) {
+        Some(sigs) => sigs.clone(),
+        None => {
+            return Err(Diagnostic::error(E_UNKNOWN_TYPE, format!("cannot find interface `{}`", iname))
+                .with_label(Label::primary(iface.span(), "not an interface in this module")));
+        }
+    };
+    // the interface is implemented even if some methods are wrong, so its
+    // uses are not reported again
+    ev.impls.insert((tname.to_string(), iname.clone()));
+    let mut implemented = Vec::new();
+    for f in fns {
+        if let AstNode::FnDecl(fident, _, param, _, _, _, _) = f {
+            let fname = ident_name(fident);
+            let mname = fname.trim_start_matches(&format!("{}::", tname)).to_string();
+            let sig = match sigs.iter().find(|m| m.0 == mname) {
+                Some(sig) => sig,
+                None => {
+                    ev.report(Diagnostic::error(E_INTERFACE_IMPL, format!("method `{}` is not a member of interface `{}`", mname, iname))
+                        .with_label(Label::primary(fident.span(), format!("not a member of `{}`", iname))));
+                    continue;
+                }
+            };
+            implemented.push(mname.clone());
+            let has_self = match param.first() { Some(AstNode::Ident(_, AstType::Ref(_), _)) => true, _ => false };
+            let params: Vec<AstType> = param.iter().skip(has_self as usize).map(ident_type).collect();
+            if !has_self || params != sig.1 || ident_type(fident) != sig.2 {
+                let found = render_sig(&mname, has_self, &params, &ident_type(fident));
+                ev.report(Diagnostic::error(E_INTERFACE_IMPL, format!("method `{}` has an incompatible signature for interface `{}`", mname, iname))
+                    .with_label(Label::primary(fident.span(), format!("found `{}`", found)))
+                    .with_note(format!("expected `{}`", render_sig(&mname, true, &sig.1, &sig.2))));
+            }
+        }
+    }
+    let missing: Vec<String> = sigs.iter().filter(|m| !implemented.contains(&m.0)).map(|m| format!("`{}`", m.0)).collect();
+    if !missing.is_empty() {
+        return Err(Diagnostic::error(E_INTERFACE_IMPL, format!("not all methods of `{}` are implemented for `{}`", iname, tname))
+            .with_label(Label::primary(iface.span(), format!("missing {}", missing.join(", ")))));
+    }
+    Ok(())
+}
+
+fn render_sig(name: &str, has_self: bool, params: &[AstType], ret: &AstType) -> String {
+    let mut args: Vec<String> = params.iter().map(|t| t.to_string()).collect();
+    if has_self { args.insert(0, "self".to_string()); }
+    let ret = if *ret == AstType::Void { String::new() } else { format!(" -> {}", ret) };
+    format!("fn {}({}){}", name, args.join(", "), ret)
+}
+
+fn check_impl(ev: &mut Env, n: &mut AstNode) -> CheckResult<()> {
+    if let AstNode::ImplDecl(_, _, fns, _) = n {
+        for f in fns.iter_mut() {
+            if let Err(diag) = check_fndecl(ev, f) { ev.report(diag); }
+        }
+    }
+    Ok(())
+}
+
+fn declare_fn(ev: &mut Env, n: &mut AstNode) -> CheckResult<()> {
+    if is_generic(n) { return declare_generic_fn(ev, n); }
+    if let AstNode::FnDecl(ident, _, ref mut param, rspan, _, ref mut link, _) = n {
+        let name = ident_name(&ident);
+        let typs = typeof_params(ev, param)?;
+        check_type(ev, &ident_type(ident), *rspan)?;
+        let proto = prototype_fn(&name, &typs);
+        if ev.global_defined(&proto) {
+            return Err(Diagnostic::error(E_REDEFINED, format!("function `{}` is defined multiple times", name))
+                .with_label(Label::primary(ident.span(), format!("redefinition of `{}`", proto))));
+        }
+        link.symbol = if link.no_mangle || name == "main" { name.clone() } else { mangle(&name, &typs) };
+        if ev.symbols.values().any(|sym| *sym == link.symbol) {
+            return Err(Diagnostic::error(E_AMBIGUOUS, format!("symbol `{}` is ambiguous", link.symbol))
+                .with_label(Label::primary(ident.span(), format!("another overload of `{}` is also exported as `{}`", name, link.symbol)))
+                .with_note("only one overload of a function can keep its unmangled name"));
+        }
+        ev.global_def(&proto, ident_type(ident));
+        ev.symbols.insert(proto, link.symbol.clone());
+        ev.overloads.entry(name).or_default().push(typs);
+    }
+    Ok(())
+}
+
+// Generic functions get no symbol of their own; a call instantiates them
+// with the argument types, see `instantiate`.
+fn declare_generic_fn(ev: &mut Env, n: &mut AstNode) -> CheckResult<()> {
+    if let AstNode::FnDecl(ident, generics, ref mut param, rspan, _, _, _) = n {
+        check_bounds_exist(ev, generics, ident.span())?;
+        ev.type_params = generics.clone();
+        let typs = typeof_params(ev, param);
+        let ret = check_type(ev, &ident_type(ident), *rspan);
+        ev.type_params.clear();
+        let typs = typs?;
+        ret?;
+        // type arguments are only inferred from the arguments
+        for (tp, _) in generics.iter() {
+            if !typs.iter().any(|t| mentions_type(t, tp)) {
+                return Err(Diagnostic::error(E_CANNOT_INFER, format!("type parameter `{}` is not used by any parameter", tp))
+                    .with_label(Label::primary(ident.span(), format!("`{}` cannot be inferred at a call", tp))));
+            }
         }
+        ev.generics.entry(ident_name(ident)).or_default().push(n.clone());
+    }
+    Ok(())
+}
+
+fn check_bounds_exist(ev: &Env, generics: &Generics, span: Span) -> CheckResult<()> {
+    for (tp, bound) in generics.iter() {
+        if let Some(iface) = bound {
+            if !ev.interfaces.contains_key(iface) {
+                return Err(Diagnostic::error(E_UNKNOWN_TYPE, format!("cannot find interface `{}`", iface))
+                    .with_label(Label::primary(span, format!("in the bound of `{}`", tp))));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn mentions_type(typ: &AstType, name: &str) -> bool {
+    match typ {
+        AstType::Ext(n) => n == name,
+        AstType::Generic(_, args) => args.iter().any(|t| mentions_type(t, name)),
+        AstType::Ref(inner) => mentions_type(inner, name),
+        _ => false,
+    }
+}
+
+// overload key of a function, `foo:-int-float`
+fn prototype_fn(name: &str, typs: &[AstType]) -> String {
+    let mut key = vec![":".to_string()];
+    key.extend(typs.iter().map(|t| t.to_string()));
+    name.to_string() + &key.join("-")
+}
+
+// a generic body is checked once, with its type parameters standing for any
+// type that satisfies their bounds
+fn check_fndecl(ev: &mut Env, n: &mut AstNode) -> CheckResult<()> {
+    if let AstNode::FnDecl(ident, generics, ref mut param, rspan, block, _, _) = n {
+        let rtyp = ident_type(&ident);
+        ev.type_params = generics.clone();
+        // an unknown return type is reported by `declare_fn`, returns are not checked against it
+        let known = check_type(ev, &rtyp, *rspan).is_ok();
+        ev.enter_scope();
+        define_local_var(ev, &param);
+        ev.fn_ret = if known { Some((rtyp.clone(), *rspan)) } else { None };
         check_stmtblock(ev, block);
+        ev.fn_ret = None;
+        check_init(ev, block);
         // println!("[leave_scope]:\n{}", ev);
         ev.leave_scope();
+        ev.type_params.clear();
+        if known && rtyp != AstType::Void && !block_returns(block) {
+            return Err(Diagnostic::error(E_MISSING_RETURN, "not all paths return a value")
+                .with_label(Label::primary(ident.span(), format!("expected {} on every path", rtyp)))
+                .with_note("only functions without a return type may end without `return`"));
+        }
+    }
+    Ok(())
+}
+
+// whether control never gets past `n`: a jump, or an `if` or `match` whose
+// branches all end in one
+fn diverges(n: &AstNode) -> bool {
+    let ends = |block: &Vec<AstNode>| block.last().map_or(false, diverges);
+    match n {
+        AstNode::ReturnStmt(_, _, _) | AstNode::Break(_, _) | AstNode::Continue(_, _) => true,
+        AstNode::IfStmt(_, tblock, fblock, _, _) => ends(tblock) && ends(fblock),
+        AstNode::MatchExpr(_, arms, _, _) => !arms.is_empty() && arms.iter().all(|arm| ends(&arm.1)),
+        _ => false,
     }
 }
 
+fn block_returns(block: &Vec<AstNode>) -> bool {
+    block.iter().any(|stmt| match stmt {
+        AstNode::ReturnStmt(_, _, _) => true,
+        AstNode::IfStmt(_, tblock, fblock, _, _) => block_returns(tblock) && block_returns(fblock),
+        // arms are exhaustive once checked
+        AstNode::MatchExpr(_, arms, _, _) => arms.iter().all(|arm| block_returns(&arm.1)),
+        // `while true` and `loop` only end through `break`
+        AstNode::WhileStmt(cond, body, label, _) => {
+            if let AstNode::Bool(true, _) = **cond { !breaks_out(body, label) } else { false }
+        }
+        AstNode::LoopStmt(body, label, _) => !breaks_out(body, label),
+        _ => false,
+    })
+}
+
+// whether `block`, the body of a loop labeled `label`, contains a `break` leaving that loop
+fn breaks_out(block: &Vec<AstNode>, label: &LoopLabel) -> bool {
+    block.iter().any(|stmt| match stmt {
+        AstNode::Break(None, _) => true,
+        AstNode::Break(l, _) => l == label,
+        AstNode::IfStmt(_, tblock, fblock, _, _) => breaks_out(tblock, label) || breaks_out(fblock, label),
+        AstNode::MatchExpr(_, arms, _, _) => arms.iter().any(|arm| breaks_out(&arm.1, label)),
+        // an unlabeled `break` in a nested loop leaves the nested loop
+        AstNode::WhileStmt(_, body, _, _) | AstNode::LoopStmt(body, _, _) |
+        AstNode::ForStmt(_, _, _, _, _, body, _, _) => label.is_some() && breaks_labeled(body, label),
+        _ => false,
+    })
+}
+
+fn breaks_labeled(block: &Vec<AstNode>, label: &LoopLabel) -> bool {
+    block.iter().any(|stmt| match stmt {
+        AstNode::Break(l, _) => l == label,
+        AstNode::IfStmt(_, tblock, fblock, _, _) => breaks_labeled(tblock, label) || breaks_labeled(fblock, label),
+        AstNode::MatchExpr(_, arms, _, _) => arms.iter().any(|arm| breaks_labeled(&arm.1, label)),
+        AstNode::WhileStmt(_, body, _, _) | AstNode::LoopStmt(body, _, _) |
+        AstNode::ForStmt(_, _, _, _, _, body, _, _) => breaks_labeled(body, label),
+        _ => false,
+    })
+}
+
 fn check_stmtblock(ev: &mut Env, block: &mut Vec<AstNode>) {
-    for stmt in block { check_expr(ev, stmt) }
+    let mut diverged = false;
+    for stmt in block {
+        if diverged {
+            ev.report(Diagnostic::warning(W_UNREACHABLE, "unreachable statement")
+                .with_label(Label::primary(stmt.span(), "unreachable statement")));
+            diverged = false;
+        }
+        if let Err(diag) = check_expr(ev, stmt) { ev.report(diag); }
+        if diverges(stmt) { diverged = true; }
+    }
 }
 
-fn check_expr(ev: &mut Env, stmt: &mut AstNode) {
+// Definite initialization: a `let x: T;` must be assigned on every path that
+// reaches a read of `x`. Runs on a function body after it is type checked.
+struct InitState {
+    // declarations in scope, innermost last: name, declaration span, assigned
+    vars: Vec<(String, Span, bool)>,
+    // enclosing loops, with the assigned flags at each `break` that leaves them
+    breaks: Vec<(LoopLabel, Vec<Vec<bool>>)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+fn check_init(ev: &mut Env, block: &Vec<AstNode>) {
+    let mut st = InitState { vars: Vec::new(), breaks: Vec::new(), diagnostics: Vec::new() };
+    init_block(&mut st, block);
+    for diag in st.diagnostics { ev.report(diag); }
+}
+
+fn init_flags(st: &InitState) -> Vec<bool> {
+    st.vars.iter().map(|v| v.2).collect()
+}
+
+fn set_init_flags(st: &mut InitState, flags: &[bool]) {
+    for (var, flag) in st.vars.iter_mut().zip(flags) { var.2 = *flag; }
+}
+
+// assigned where both paths meet only if assigned on both
+fn meet_flags(a: &[bool], b: &[bool]) -> Vec<bool> {
+    a.iter().zip(b).map(|(x, y)| *x && *y).collect()
+}
+
+// each `init_*` returns false when control never gets past the node
+fn init_block(st: &mut InitState, block: &Vec<AstNode>) -> bool {
+    let depth = st.vars.len();
+    let mut live = true;
+    for stmt in block {
+        if !init_stmt(st, stmt) { live = false; break; }
+    }
+    st.vars.truncate(depth);
+    live
+}
+
+fn init_stmt(st: &mut InitState, stmt: &AstNode) -> bool {
     match stmt {
-        AstNode::VarDecl(_, _, _) => { check_vardecl(ev, stmt, false); }
-        AstNode::Assignment(_, _) => { check_assignstmt(ev, stmt); },
-        AstNode::IfStmt(ref mut cond, ref mut tblock, ref mut fblock) => {
-            assert!(typeof_bool_expr(ev, cond) != AstType::Undef);
-            check_stmtblock(ev, tblock);
-            check_stmtblock(ev, fblock);
+        AstNode::VarDecl(var, val, _, _) => {
+            let live = init_expr(st, val);
+            st.vars.push((ident_name(var), var.span(), !nil_node(val)));
+            live
         }
-        AstNode::WhileStmt(ref mut cond, ref mut block) => {
-            assert!(typeof_bool_expr(ev, cond) != AstType::Undef);
-            check_stmtblock(ev, block);
+        AstNode::Assignment(var, val, _) => {
+            let live = init_expr(st, val);
+            // assigning a field needs the whole struct initialized
+            if let AstNode::FieldAccess(_, _, _, _, _) = **var { return init_expr(st, var) && live; }
+            let name = ident_name(var);
+            if let Some(v) = st.vars.iter_mut().rev().find(|v| v.0 == name) { v.2 = true; }
+            live
         }
-        AstNode::ReturnStmt(ref mut expr, ref mut typ) => {
-            *typ = typeof_value_expr(ev, expr);
+        AstNode::IfStmt(cond, tblock, fblock, _, _) => {
+            if !init_expr(st, cond) { return false; }
+            let before = init_flags(st);
+            let tlive = init_block(st, tblock);
+            let tflags = init_flags(st);
+            set_init_flags(st, &before);
+            let flive = init_block(st, fblock);
+            match (tlive, flive) {
+                (true, true) => { let flags = meet_flags(&tflags, &init_flags(st)); set_init_flags(st, &flags); }
+                (true, false) => set_init_flags(st, &tflags),
+                (false, true) => (),
+                (false, false) => return false,
+            }
+            true
         }
-        _ => {
-            if typeof_bool_expr(ev, stmt) == AstType::Undef {
-                panic!("typeof_bool_expr");
+        AstNode::MatchExpr(val, arms, _, _) => {
+            if !init_expr(st, val) { return false; }
+            let before = init_flags(st);
+            let mut after: Option<Vec<bool>> = None;
+            for (pat, body) in arms {
+                set_init_flags(st, &before);
+                // pattern bindings are initialized and shadow outer names
+                let depth = st.vars.len();
+                init_pattern(st, pat);
+                let live = init_block(st, body);
+                st.vars.truncate(depth);
+                if live {
+                    let flags = init_flags(st);
+                    after = Some(match after {
+                        Some(a) => meet_flags(&a, &flags),
+                        None => flags,
+                    });
+                }
             }
+            match after {
+                Some(flags) => { set_init_flags(st, &flags); true }
+                None => false,
+            }
+        }
+        AstNode::WhileStmt(cond, body, label, _) => {
+            if !init_expr(st, cond) { return false; }
+            let exits = if let AstNode::Bool(true, _) = **cond { false } else { true };
+            init_loop(st, body, label, exits)
         }
+        AstNode::LoopStmt(body, label, _) => init_loop(st, body, label, false),
+        AstNode::ForStmt(var, start, end, _, step, body, label, _) => {
+            if !(init_expr(st, start) && init_expr(st, end) && init_expr(st, step)) { return false; }
+            st.vars.push((ident_name(var), var.span(), true));
+            init_loop(st, body, label, true);
+            st.vars.pop();
+            true
+        }
+        AstNode::Break(label, _) => {
+            let flags = init_flags(st);
+            let target = match label {
+                None => st.breaks.last_mut(),
+                Some(_) => st.breaks.iter_mut().rev().find(|b| b.0 == *label),
+            };
+            if let Some((_, states)) = target { states.push(flags); }
+            false
+        }
+        AstNode::Continue(_, _) => false,
+        AstNode::ReturnStmt(val, _, _) => { init_expr(st, val); false }
+        _ => init_expr(st, stmt),
     }
 }
 
-fn check_assignstmt(ev: &mut Env, n: &mut AstNode) {
-    if let AstNode::Assignment(ref mut var, ref mut valexpr) = n {
-        let vname = ident_name(var);
-        let ltyp = ev.lookup(&vname).unwrap();
-        let rtyp = typeof_value_expr(ev, valexpr);
-        match ltyp {
-            AstType::Ext(_) | AstType::Undef => {
-                update_ident_type(var, rtyp.clone());
-                ev.update(var, rtyp);
-            },
-            _ => {
-                if ltyp != rtyp { unreachable!("unmatch {} {}", ltyp, rtyp); }
+fn init_pattern(st: &mut InitState, pat: &Pattern) {
+    match pat {
+        Pattern::Binding(name, span) => st.vars.push((name.clone(), *span, true)),
+        Pattern::Variant(_, _, subs, _, _) => subs.iter().for_each(|sub| init_pattern(st, sub)),
+        Pattern::Wildcard(_) => (),
+    }
+}
+
+// the body may run zero times unless the loop only `exits` through `break`
+fn init_loop(st: &mut InitState, body: &Vec<AstNode>, label: &LoopLabel, exits: bool) -> bool {
+    let before = init_flags(st);
+    st.breaks.push((label.clone(), Vec::new()));
+    init_block(st, body);
+    let (_, states) = st.breaks.pop().unwrap();
+    let mut after = if exits { Some(before.clone()) } else { None };
+    for state in states {
+        let state = &state[..before.len()];
+        after = Some(match after {
+            Some(flags) => meet_flags(&flags, state),
+            None => state.to_vec(),
+        });
+    }
+    match after {
+        Some(flags) => { set_init_flags(st, &flags); true }
+        None => false,
+    }
+}
+
+fn init_expr(st: &mut InitState, expr: &AstNode) -> bool {
+    match expr {
+        AstNode::Ident(name, _, span) => {
+            if let Some(v) = st.vars.iter_mut().rev().find(|v| v.0 == *name) {
+                if !v.2 {
+                    st.diagnostics.push(Diagnostic::error(E_UNINITIALIZED, format!("used binding `{}` is possibly-uninitialized", name))
+                        .with_label(Label::primary(*span, format!("`{}` used here but it is possibly-uninitialized", name)))
+                        .with_label(Label::secondary(v.1, "binding declared here but left uninitialized")));
+                    // report each binding once
+                    v.2 = true;
+                }
+            }
+            true
+        }
+        AstNode::BinaryOp(lhs, op, rhs, _, _) => {
+            if !init_expr(st, lhs) { return false; }
+            if *op == Operator::AND || *op == Operator::OR {
+                // the right operand may not run
+                let before = init_flags(st);
+                init_expr(st, rhs);
+                set_init_flags(st, &before);
+                return true;
             }
+            init_expr(st, rhs)
         }
+        AstNode::UnaryOp(_, val, _, _) | AstNode::TailExpr(val, _) |
+        AstNode::FieldAccess(val, _, _, _, _) => init_expr(st, val),
+        AstNode::StructLit(_, fields, _) => fields.iter().all(|(_, val)| init_expr(st, val)),
+        AstNode::FnCall(_, args, _, _) => args.iter().all(|arg| init_expr(st, arg)),
+        AstNode::MethodCall(obj, _, args, _, _) => init_expr(st, obj) && args.iter().all(|arg| init_expr(st, arg)),
+        AstNode::EnumLit(_, _, args, _, _) => args.iter().all(|arg| init_expr(st, arg)),
+        AstNode::DynCast(val, _, _, _) => init_expr(st, val),
+        AstNode::DynCall(obj, _, _, args, _) => init_expr(st, obj) && args.iter().all(|arg| init_expr(st, arg)),
+        AstNode::IfStmt(_, _, _, _, _) | AstNode::MatchExpr(_, _, _, _) => init_stmt(st, expr),
+        _ => true,
     }
 }
 
-fn check_vardecl(ev: &mut Env, n: &mut AstNode, global: bool) {
-    if let AstNode::VarDecl(ref mut var, ref mut val, ref mut typ) = n {
-        let vname = ident_name(&var);
-        if ev.can_lookup(&vname) {
-            panic!("redefine '{}'", vname);
-        }
-        if global { ev.global_def(&vname, typ.clone()); } else { ev.local_def(&vname, typ.clone()); }
-        let valty = typeof_value_expr(ev, val);
-        let ty = if valty != AstType::Nil { valty.clone() } else { typ.clone() };
-        ev.update(var, ty);
+fn check_return_type(ev: &mut Env, expr: &mut AstNode, typ: &AstType, span: Span) -> CheckResult<()> {
+    let (expected, ret_span) = match ev.fn_ret.clone() {
+        Some(ret) => ret,
+        None => return Ok(()),
+    };
+    // a bare `return` yields no value
+    let found = if *typ == AstType::Nil { AstType::Void } else { typ.clone() };
+    if found != expected && !coerce(ev, &expected, &found, expr) {
+        let span = if nil_node(expr) { span } else { expr.span() };
+        return Err(mismatched_types(&expected, &found, span)
+            .with_label(Label::secondary(ret_span, format!("expected {} because of the return type", expected))));
     }
+    Ok(())
 }
 
-fn typeof_value_expr(ev: &mut Env, n: &mut AstNode) -> AstType {
-    match n {
-        AstNode::BinaryOp(_, _, _, _) => {
-            // if !is_math_op(*op) { unreachable!("unmatch math Operator{}", op); }
-            typeof_binary_op(ev, n)
-        },
-        _ => typeof_valobj(ev, n),
+fn check_scoped_block(ev: &mut Env, block: &mut Vec<AstNode>) {
+    ev.enter_scope();
+    check_stmtblock(ev, block);
+    ev.leave_scope();
+}
+
+fn check_expr(ev: &mut Env, stmt: &mut AstNode) -> CheckResult<()> {
+    match stmt {
+        AstNode::VarDecl(_, _, _, _) => { check_vardecl(ev, stmt, false)?; }
+        AstNode::Assignment(_, _, _) => { check_assignstmt(ev, stmt)?; },
+        AstNode::IfStmt(ref mut cond, ref mut tblock, ref mut fblock, ref mut typ, _) => {
+            typeof_bool_expr(ev, cond)?;
+            check_scoped_block(ev, tblock);
+            check_scoped_block(ev, fblock);
+            *typ = AstType::Void;
+        }
+        AstNode::MatchExpr(_, _, _, _) => { typeof_match(ev, stmt, false)?; }
+        AstNode::TailExpr(ref mut expr, _) => { typeof_value_expr(ev, expr)?; }
+        AstNode::WhileStmt(ref mut cond, ref mut block, label, span) => {
+            typeof_bool_expr(ev, cond)?;
+            check_loop_body(ev, label, block, *span);
+        }
+        AstNode::LoopStmt(ref mut block, label, span) => {
+            check_loop_body(ev, label, block, *span);
+        }
+        AstNode::ForStmt(_, _, _, _, _, _, _, _) => { check_forstmt(ev, stmt)?; }
+        AstNode::Break(label, span) => { check_loop_jump(ev, "break", label, *span)?; }
+        AstNode::Continue(label, span) => { check_loop_jump(ev, "continue", label, *span)?; }
+        AstNode::ReturnStmt(ref mut expr, ref mut typ, span) => {
+            let expected = ev.fn_ret.clone().map_or(AstType::Undef, |ret| ret.0);
+            *typ = typeof_expected(ev, expr, &expected)?;
+            check_return_type(ev, expr, typ, *span)?;
+        }
+        _ => { typeof_value_expr(ev, stmt)?; }
     }
+    Ok(())
 }
 
-fn typeof_bool_expr(ev: &mut Env, n: &mut AstNode) -> AstType {
-    match n {
-        AstNode::BinaryOp(_, op, _, _) => {
-            if !is_logic_op(*op) { unreachable!("unmatch logic Operator{}", op); }
-            typeof_binary_op(ev, n)
-        },
-        _ => typeof_valobj(ev, n),
+// a labeled loop's `span` starts at its label
+fn check_loop_body(ev: &mut Env, label: &LoopLabel, block: &mut Vec<AstNode>, span: Span) {
+    if let Some(name) = label {
+        if ev.loops.contains(label) {
+            let span = Span::new(span.file, span.start, span.start + name.len() + 1);
+            ev.report(Diagnostic::error(E_REDEFINED, format!("label `'{}` shadows a label of an enclosing loop", name))
+                .with_label(Label::primary(span, format!("`'{}` is already in scope", name)))
+                .with_note("`break` and `continue` could not reach the outer loop"));
+        }
     }
+    ev.loops.push(label.clone());
+    check_scoped_block(ev, block);
+    ev.loops.pop();
 }
 
+fn check_loop_jump(ev: &mut Env, kw: &str, label: &LoopLabel, span: Span) -> CheckResult<()> {
+    if ev.loops.is_empty() {
+        return Err(Diagnostic::error(E_OUTSIDE_LOOP, format!("`{}` outside of a loop", kw))
+            .with_label(Label::primary(span, format!("cannot `{}` outside of a loop", kw))));
+    }
+    if let Some(name) = label {
+        if !ev.loops.contains(label) {
+            return Err(Diagnostic::error(E_UNDECLARED_LABEL, format!("use of undeclared label `'{}`", name))
+                .with_label(Label::primary(span, format!("no enclosing loop is labeled `'{}`", name))));
+        }
+    }
+    Ok(())
+}
 
-fn typeof_valobj(ev: &mut Env, n: &mut AstNode) -> AstType {
-    match n {
-        AstNode::Int(_) => AstType::Int,
-        AstNode::Float(_) => AstType::Float,
-        AstNode::Str(_) => AstType::Str,
-        AstNode::Ident(var, _) => {
-            if !ev.can_lookup(&var) {
-                panic!("cann't resolve {}", var);
+// `for i in a..b step s`: bounds and step are int, `i` lives in the loop's own scope
+fn check_forstmt(ev: &mut Env, n: &mut AstNode) -> CheckResult<()> {
+    if let AstNode::ForStmt(ref mut var, ref mut start, ref mut end, _, ref mut step, ref mut block, label, span) = n {
+        for bound in [start, end].iter_mut() {
+            let typ = typeof_value_expr(ev, bound)?;
+            if typ != AstType::Int {
+                return Err(mismatched_types(&AstType::Int, &typ, bound.span())
+                    .with_note("range bounds must be int"));
             }
-            ev.lookup(&var).unwrap()
         }
-        AstNode::FnCall(ident, param) => {
-            let proto = prototype_fn(ev, ident_name(&ident), param);
-            match ev.global_resolve(&proto) {
-                Some(typ) => typ.clone(),
-                None => unreachable!("cann't resolve fn proto:{}", proto),
+        if !nil_node(step) {
+            let typ = typeof_value_expr(ev, step)?;
+            if typ != AstType::Int {
+                return Err(mismatched_types(&AstType::Int, &typ, step.span())
+                    .with_note("the step of a range must be int"));
             }
-        },
-        AstNode::BinaryOp(_, _, _, _) => typeof_value_expr(ev, n),
-        AstNode::Nil => AstType::Nil,
-        _ => unreachable!(),
+            // a step that is not constant is not checked
+            if let Ok(AstNode::Int(v, _)) = fold_const(ev, step) {
+                if v <= 0 {
+                    return Err(Diagnostic::error(E_RANGE_STEP, "range step must be positive")
+                        .with_label(Label::primary(step.span(), format!("the step is {}", v)))
+                        .with_note("a range counts up from its start, a step of 0 never ends and a negative one never runs"));
+                }
+            }
+        }
+        ev.enter_scope();
+        ev.local_def(&ident_name(var), AstType::Int);
+        update_ident_type(var, AstType::Int);
+        check_loop_body(ev, label, block, *span);
+        ev.leave_scope();
     }
+    Ok(())
 }
 
-fn typeof_binary_op(ev: &mut Env, n: &mut AstNode) -> AstType {
-    if let AstNode::BinaryOp(ref mut lhs, _, ref mut rhs, ref mut typ) = n {
-        let rtyp = typeof_valobj(ev, rhs);
-        let ltyp = match *lhs.clone() {
-            AstNode::BinaryOp(_, _, _, _) => typeof_binary_op(ev, lhs),
-            _ => typeof_valobj(ev, lhs),
+fn check_assignstmt(ev: &mut Env, n: &mut AstNode) -> CheckResult<()> {
+    if let AstNode::Assignment(ref mut var, ref mut valexpr, _) = n {
+        if let AstNode::FieldAccess(_, _, _, _, _) = **var {
+            return check_field_assign(ev, var, valexpr);
+        }
+        let vname = ident_name(var);
+        if ev.const_value(&vname).is_some() {
+            return Err(Diagnostic::error(E_ASSIGN_CONST, format!("cannot assign to constant `{}`", vname))
+                .with_label(Label::primary(var.span(), "cannot assign to this constant")));
+        }
+        let ltyp = match ev.lookup(&vname) {
+            Some(typ) => typ,
+            None => return Err(unresolved_name(&vname, var.span())),
         };
-        if rtyp != ltyp {
-            unreachable!("unexpected {} == {}", ltyp, rtyp);
+        let rtyp = typeof_expected(ev, valexpr, &ltyp)?;
+        if rtyp == AstType::Void { return Err(void_value(valexpr.span())); }
+        match ltyp {
+            AstType::Undef => {
+                update_ident_type(var, rtyp.clone());
+                ev.update(var, rtyp);
+            },
+            _ => {
+                if ltyp != rtyp && !coerce(ev, &ltyp, &rtyp, valexpr) {
+                    return Err(mismatched_types(&ltyp, &rtyp, valexpr.span())
+                        .with_label(Label::secondary(var.span(), format!("`{}` is declared as {}", vname, ltyp))));
+                }
+            }
         }
-        *typ = rtyp.clone();
-        return rtyp;
     }
-    unreachable!();
+    Ok(())
 }
 
-fn _is_math_op(op: Operator) -> bool {
-    match op {
-        Operator::PLUS | Operator::SUB |
-        Operator::MUL | Operator::DIV => true,
+fn check_field_assign(ev: &mut Env, place: &mut AstNode, valexpr: &mut AstNode) -> CheckResult<()> {
+    if !is_place(place) {
+        return Err(Diagnostic::error(E_INVALID_ASSIGN, "invalid left-hand side of assignment")
+            .with_label(Label::primary(place.span(), "cannot assign to this expression"))
+            .with_note("only fields of variables can be assigned"));
+    }
+    let ltyp = typeof_value_expr(ev, place)?;
+    let rtyp = typeof_expected(ev, valexpr, &ltyp)?;
+    if rtyp == AstType::Void { return Err(void_value(valexpr.span())); }
+    if ltyp != rtyp && !coerce(ev, &ltyp, &rtyp, valexpr) {
+        return Err(mismatched_types(&ltyp, &rtyp, valexpr.span())
+            .with_label(Label::secondary(place.span(), format!("this field is {}", ltyp))));
+    }
+    Ok(())
+}
+
+// a variable, or a field of one
+fn is_place(n: &AstNode) -> bool {
+    match n {
+        AstNode::Ident(_, _, _) => true,
+        AstNode::FieldAccess(obj, _, _, _, _) => is_place(obj),
         _ => false,
     }
 }
 
-fn define_local_var(ev: &mut Env, p: &Vec<AstNode>) {
-    for var in p {
-        if let AstNode::Ident(name, typ) = var {
-            ev.local_def(&name, typ.clone());
+fn check_vardecl(ev: &mut Env, n: &mut AstNode, global: bool) -> CheckResult<()> {
+    let mut diverging = None;
+    if let AstNode::VarDecl(ref mut var, ref mut val, ref mut typ, _) = n {
+        let vname = ident_name(&var);
+        let defined = if global { ev.global_defined(&vname) } else { ev.local_defined(&vname) };
+        if defined {
+            return Err(Diagnostic::error(E_REDEFINED, format!("variable `{}` is defined multiple times", vname))
+                .with_label(Label::primary(var.span(), format!("`{}` redefined here", vname)))
+                .with_note("a name can only be shadowed in a nested block"));
         }
+        if *typ != AstType::Undef { check_type(ev, typ, var.span())?; }
+        // the name is not in scope in its own initializer; after a bad
+        // initializer, or one that never yields, it is bound as Undef, see
+        // `typeof_valobj`
+        let valty = match typeof_expected(ev, val, typ) {
+            Ok(AstType::Void) if diverges(val) => Ok(AstType::Undef),
+            Ok(AstType::Void) => Err(void_value(val.span())),
+            res => res,
+        };
+        let ty = match valty {
+            Ok(AstType::Nil) => typ.clone(),
+            Ok(ref valty) => valty.clone(),
+            Err(_) => AstType::Undef,
+        };
+        if global { ev.global_def(&vname, ty.clone()); } else { ev.local_def(&vname, ty.clone()); }
+        update_ident_type(var, ty);
+        valty?;
+        // globals are initialized statically
+        if global && !nil_node(val) { **val = fold_const(ev, val)?; }
+        else if diverges(val) { diverging = Some((**val).clone()); }
     }
+    // only the initializer runs, the name is never bound
+    if let Some(val) = diverging { *n = val; }
+    Ok(())
 }
 
-fn typeof_param(ev: &mut Env, n: AstNode) -> AstType {
-    let mut rtyp = AstType::Undef;
-    if let AstNode::Ident(_, typ) = n {
-        match typ {
-            AstType::Ext(name) => {
-                rtyp = ev.lookup(&name).unwrap();
-            },
-            _ => rtyp = typ
+fn check_constdecl(ev: &mut Env, n: &mut AstNode) -> CheckResult<()> {
+    if let AstNode::ConstDecl(ref mut var, ref mut val, typ, _) = n {
+        let vname = ident_name(&var);
+        if ev.global_defined(&vname) {
+            return Err(Diagnostic::error(E_REDEFINED, format!("constant `{}` is defined multiple times", vname))
+                .with_label(Label::primary(var.span(), format!("`{}` redefined here", vname))));
         }
+        check_type(ev, typ, var.span())?;
+        let valty = typeof_value_expr(ev, val)?;
+        if valty != *typ {
+            return Err(mismatched_types(typ, &valty, val.span())
+                .with_label(Label::secondary(var.span(), format!("`{}` is declared as {}", vname, typ))));
+        }
+        **val = fold_const(ev, val)?;
+        ev.global_def(&vname, typ.clone());
+        ev.consts.insert(vname, *val.clone());
     }
-    rtyp
+    Ok(())
 }
 
-fn join_param(ev: &mut Env, p: &mut Vec<AstNode>) -> String {
-    let mut typs = vec![":".to_string()];
-    let mut typ: AstType;
-    for item in p {
-        match item {
-            AstNode::Ident(_, _) => { typ = typeof_param(ev, item.clone()); },
-            _ => { typ = typeof_value_expr(ev, item); }
+// evaluates a type checked initializer of a global or constant to a literal
+fn fold_const(ev: &mut Env, n: &AstNode) -> CheckResult<AstNode> {
+    let not_const = |span: Span, what: &str| {
+        Diagnostic::error(E_NOT_CONST, "initializer is not a constant expression")
+            .with_label(Label::primary(span, format!("{} not allowed in a constant", what)))
+            .with_note("globals and constants are initialized at compile time")
+    };
+    let folded = match n {
+        AstNode::Int(_, _) | AstNode::Float(_, _) | AstNode::Bool(_, _) => n.clone(),
+        AstNode::Ident(name, _, span) => match ev.const_value(name) {
+            Some(val) => val.clone(),
+            None => return Err(not_const(*span, &format!("`{}` is a variable,", name))),
+        },
+        AstNode::UnaryOp(op, val, _, span) => match (op, fold_const(ev, val)?) {
+            (Operator::SUB, AstNode::Int(v, _)) => match v.checked_neg() {
+                Some(v) => AstNode::Int(v, *span),
+                None => return Err(const_overflow(*span)),
+            },
+            (Operator::SUB, AstNode::Float(v, _)) => AstNode::Float(-v, *span),
+            (Operator::NOT, AstNode::Bool(v, _)) => AstNode::Bool(!v, *span),
+            _ => unreachable!("[fold_const] {:?}", n),
+        },
+        AstNode::BinaryOp(lhs, op, rhs, _, span) => {
+            let span = *span;
+            match (fold_const(ev, lhs)?, fold_const(ev, rhs)?) {
+                (AstNode::Int(a, _), AstNode::Int(b, _)) => {
+                    let res = match op {
+                        Operator::PLUS => a.checked_add(b),
+                        Operator::SUB => a.checked_sub(b),
+                        Operator::MUL => a.checked_mul(b),
+                        Operator::DIV | Operator::REM if b == 0 => {
+                            return Err(Diagnostic::error(E_NOT_CONST, "evaluation of constant value failed")
+                                .with_label(Label::primary(rhs.span(), "attempt to divide by zero")));
+                        }
+                        Operator::DIV => a.checked_div(b),
+                        Operator::REM => a.checked_rem(b),
+                        _ => return Ok(AstNode::Bool(fold_compare(*op, a, b), span)),
+                    };
+                    match res {
+                        Some(v) => AstNode::Int(v, span),
+                        None => return Err(const_overflow(span)),
+                    }
+                }
+                (AstNode::Float(a, _), AstNode::Float(b, _)) => match op {
+                    Operator::PLUS => AstNode::Float(a + b, span),
+                    Operator::SUB => AstNode::Float(a - b, span),
+                    Operator::MUL => AstNode::Float(a * b, span),
+                    Operator::DIV => AstNode::Float(a / b, span),
+                    Operator::REM => AstNode::Float(a % b, span),
+                    _ => AstNode::Bool(fold_compare(*op, a, b), span),
+                },
+                (AstNode::Bool(a, _), AstNode::Bool(b, _)) => match op {
+                    Operator::AND => AstNode::Bool(a && b, span),
+                    Operator::OR => AstNode::Bool(a || b, span),
+                    _ => AstNode::Bool(fold_compare(*op, a, b), span),
+                },
+                _ => unreachable!("[fold_const] {:?}", n),
+            }
+        }
+        AstNode::StructLit(ident, fields, span) => {
+            let mut folded = Vec::new();
+            for (name, val) in fields {
+                folded.push((name.clone(), fold_const(ev, val)?));
+            }
+            AstNode::StructLit(ident.clone(), folded, *span)
+        }
+        AstNode::FnCall(_, _, _, span) => return Err(not_const(*span, "function calls are")),
+        AstNode::MethodCall(_, _, _, _, span) | AstNode::DynCall(_, _, _, _, span) => {
+            return Err(not_const(*span, "method calls are"));
+        }
+        AstNode::DynCast(_, _, _, span) => return Err(not_const(*span, "`dyn` values are")),
+        AstNode::IfStmt(_, _, _, _, span) => return Err(not_const(*span, "`if` is")),
+        AstNode::MatchExpr(_, _, _, span) => return Err(not_const(*span, "`match` is")),
+        AstNode::EnumLit(_, _, args, _, span) if !args.is_empty() => {
+            return Err(not_const(*span, "enum variants with fields are"));
         }
-        typs.push(typ.to_string());
+        AstNode::EnumLit(_, _, _, _, _) => n.clone(),
+        _ => return Err(not_const(n.span(), "this expression is")),
+    };
+    Ok(folded)
+}
+
+fn fold_compare<T: PartialOrd>(op: Operator, a: T, b: T) -> bool {
+    match op {
+        Operator::EQ => a == b,
+        Operator::NE => a != b,
+        Operator::LT => a < b,
+        Operator::LE => a <= b,
+        Operator::GT => a > b,
+        Operator::GE => a >= b,
+        _ => unreachable!("[fold_compare] {}", op),
+    }
+}
+
+fn const_overflow(span: Span) -> Diagnostic {
+    Diagnostic::error(E_NOT_CONST, "evaluation of constant value failed")
+        .with_label(Label::primary(span, "this arithmetic operation will overflow"))
+}
+
+fn typeof_value_expr(ev: &mut Env, n: &mut AstNode) -> CheckResult<AstType> {
+    match n {
+        AstNode::BinaryOp(_, _, _, _, _) => {
+            // if !is_math_op(*op) { unreachable!("unmatch math Operator{}", op); }
+            typeof_binary_op(ev, n)
+        },
+        _ => typeof_valobj(ev, n),
+    }
+}
+
+// conditions of `if` and `while`
+fn typeof_bool_expr(ev: &mut Env, n: &mut AstNode) -> CheckResult<AstType> {
+    let typ = typeof_value_expr(ev, n)?;
+    if typ != AstType::Bool {
+        return Err(mismatched_types(&AstType::Bool, &typ, n.span())
+            .with_note("conditions must be bool"));
     }
-    return typs.join("-");
+    Ok(typ)
 }
 
 
+fn typeof_valobj(ev: &mut Env, n: &mut AstNode) -> CheckResult<AstType> {
+    let typ = match n {
+        AstNode::Int(v, span) => {
+            if *v > i32::MAX as i64 {
+                return Err(Diagnostic::error(E_LITERAL_RANGE, "integer literal is too large")
+                    .with_label(Label::primary(*span, format!("`{}` does not fit in an int literal", v)))
+                    .with_note("int literals range from -2147483648 to 2147483647"));
+            }
+            AstType::Int
+        }
+        AstNode::Float(_, _) => AstType::Float,
+        AstNode::Bool(_, _) => AstType::Bool,
+        AstNode::Str(_, _) => AstType::Str,
+        AstNode::Ident(var, _, span) => {
+            match ev.lookup(&var) {
+                // its initializer was reported already
+                Some(AstType::Undef) => return Err(Diagnostic::reported()),
+                Some(typ) => typ,
+                None => return Err(unresolved_name(var, *span)),
+            }
+        }
+        AstNode::FnCall(_, _, _, _) => typeof_fncall(ev, n)?,
+        AstNode::MethodCall(_, _, _, _, _) => typeof_method_call(ev, n)?,
+        AstNode::StructLit(_, _, _) => typeof_struct_lit(ev, n, &AstType::Undef)?,
+        AstNode::FieldAccess(_, _, _, _, _) => typeof_field(ev, n)?,
+        AstNode::BinaryOp(_, _, _, _, _) => typeof_value_expr(ev, n)?,
+        AstNode::UnaryOp(_, _, _, _) => typeof_unary_op(ev, n)?,
+        AstNode::IfStmt(_, _, _, _, _) => typeof_if_expr(ev, n)?,
+        AstNode::MatchExpr(_, _, _, _) => typeof_match(ev, n, true)?,
+        AstNode::EnumLit(_, _, _, _, _) => typeof_enum_lit(ev, n)?,
+        AstNode::DynCast(_, _, iface, _) => AstType::Dyn(iface.clone()),
+        AstNode::DynCall(_, iface, slot, _, _) => ev.interfaces[iface.as_str()][*slot].2.clone(),
+        AstNode::Nil(_) => AstType::Nil,
+        _ => unreachable!(),
+    };
+    Ok(typ)
+}
+
+// types `n` where a value of `expected` is wanted, which a generic struct
+// literal takes its type arguments from, e.g. `Box<dyn A>` for `Box { v: s }`
+fn typeof_expected(ev: &mut Env, n: &mut AstNode, expected: &AstType) -> CheckResult<AstType> {
+    match n {
+        AstNode::StructLit(_, _, _) => typeof_struct_lit(ev, n, expected),
+        _ => typeof_value_expr(ev, n),
+    }
+}
+
+// every field initialized exactly once; fields are put in declaration order for codegen
+fn typeof_struct_lit(ev: &mut Env, n: &mut AstNode, expected: &AstType) -> CheckResult<AstType> {
+    if let AstNode::StructLit(ident, ref mut fields, span) = n {
+        let mut typ = ident_type(ident);
+        let given = match expected {
+            AstType::Generic(base, _) if *base == typ.to_string() => ev.structs.get(&expected.to_string()).cloned(),
+            _ => None,
+        };
+        let mut vtyps = Vec::new();
+        for (fname, val) in fields.iter_mut() {
+            let ftyp = given.iter().flatten().find(|f| f.0 == *fname).map(|f| f.1.clone());
+            vtyps.push(typeof_expected(ev, val, &ftyp.unwrap_or(AstType::Undef))?);
+        }
+        if given.is_some() {
+            typ = expected.clone();
+            update_ident_type(ident, typ.clone());
+        } else if let Some(generics) = ev.struct_generics.get(&typ.to_string()).cloned() {
+            typ = infer_struct_args(ev, &typ.to_string(), &generics, fields, &vtyps, *span)?;
+            update_ident_type(ident, typ.clone());
+        }
+        check_type(ev, &typ, ident.span())?;
+        let decl = match ev.structs.get(&typ.to_string()) {
+            Some(decl) => decl.clone(),
+            None => {
+                return Err(Diagnostic::error(E_MISMATCHED_TYPES, format!("`{}` is an enum, not a struct", typ))
+                    .with_label(Label::primary(ident.span(), "not a struct"))
+                    .with_note(format!("build a `{}` from one of its variants", typ)));
+            }
+        };
+        for ((name, val), vtyp) in fields.iter_mut().zip(vtyps) {
+            let ftyp = match decl.iter().find(|f| f.0 == *name) {
+                Some(f) => f.1.clone(),
+                None => {
+                    return Err(Diagnostic::error(E_NO_FIELD, format!("struct `{}` has no field named `{}`", typ, name))
+                        .with_label(Label::primary(val.span(), format!("`{}` is not a field of `{}`", name, typ))));
+                }
+            };
+            if vtyp != ftyp && !coerce(ev, &ftyp, &vtyp, val) {
+                return Err(mismatched_types(&ftyp, &vtyp, val.span())
+                    .with_note(format!("field `{}` of `{}` is {}", name, typ, ftyp)));
+            }
+        }
+        let mut ordered = Vec::new();
+        for (fname, _) in decl.iter() {
+            let found: Vec<usize> = (0..fields.len()).filter(|i| fields[*i].0 == *fname).collect();
+            match found.len() {
+                0 => {
+                    return Err(Diagnostic::error(E_NO_FIELD, format!("missing field `{}` in initializer of `{}`", fname, typ))
+                        .with_label(Label::primary(*span, format!("missing `{}`", fname))));
+                }
+                1 => ordered.push(fields[found[0]].clone()),
+                _ => {
+                    return Err(Diagnostic::error(E_REDEFINED, format!("field `{}` specified more than once", fname))
+                        .with_label(Label::primary(fields[found[1]].1.span(), "used more than once")));
+                }
+            }
+        }
+        *fields = ordered;
+        return Ok(typ);
+    }
+    unreachable!();
+}
+
+// `Pair { a: 1, b: 2 }` is a `Pair<int>`, a field that does not fit is
+// reported by the caller
+fn infer_struct_args(ev: &Env, name: &str, generics: &Generics, fields: &[(String, AstNode)], vtyps: &[AstType], span: Span) -> CheckResult<AstType> {
+    let decl = &ev.structs[name];
+    let mut map = HashMap::new();
+    for ((fname, _), vtyp) in fields.iter().zip(vtyps) {
+        if let Some(f) = decl.iter().find(|f| f.0 == *fname) {
+            unify(&f.1, vtyp, generics, &mut map);
+        }
+    }
+    let mut args = Vec::new();
+    for (tp, _) in generics.iter() {
+        match map.get(tp) {
+            Some(arg) => args.push(arg.clone()),
+            None => {
+                return Err(Diagnostic::error(E_CANNOT_INFER, format!("cannot infer type argument `{}` of `{}`", tp, name))
+                    .with_label(Label::primary(span, format!("`{}` is not given by any field", tp))));
+            }
+        }
+    }
+    Ok(AstType::Generic(name.to_string(), args))
+}
+
+fn typeof_field(ev: &mut Env, n: &mut AstNode) -> CheckResult<AstType> {
+    if let AstNode::FieldAccess(ref mut obj, name, ref mut idx, ref mut typ, span) = n {
+        let otyp = typeof_value_expr(ev, obj)?;
+        let decl = match otyp {
+            AstType::Ext(_) | AstType::Generic(_, _) => ev.structs.get(&otyp.to_string()).cloned().unwrap_or_default(),
+            _ => {
+                return Err(Diagnostic::error(E_NO_FIELD, format!("`{}` is a primitive type and has no fields", otyp))
+                    .with_label(Label::primary(obj.span(), format!("this is {}", otyp))));
+            }
+        };
+        match decl.iter().position(|f| f.0 == *name) {
+            Some(pos) => {
+                *idx = pos;
+                *typ = decl[pos].1.clone();
+            }
+            None => {
+                return Err(Diagnostic::error(E_NO_FIELD, format!("no field `{}` on type `{}`", name, otyp))
+                    .with_label(Label::primary(*span, "unknown field")));
+            }
+        }
+        return Ok(typ.clone());
+    }
+    unreachable!();
+}
+
+// overloads are resolved by exact parameter types
+fn typeof_fncall(ev: &mut Env, n: &mut AstNode) -> CheckResult<AstType> {
+    if let AstNode::FnCall(ident, ref mut param, ref mut symbol, span) = n {
+        let name = ident_name(&ident);
+        // `Shape::Circle(r)` builds an enum
+        if let Some((ename, variant)) = name.split_once("::") {
+            if ev.enums.contains_key(ename) {
+                let enum_ident = AstNode::Ident(ename.to_string(), AstType::Ext(ename.to_string()), ident.span());
+                *n = AstNode::EnumLit(Box::new(enum_ident), variant.to_string(), param.clone(), 0, *span);
+                return typeof_enum_lit(ev, n);
+            }
+        }
+        let mut typs = typeof_args(ev, param)?;
+        if !has_overloads(ev, &name) {
+            return Err(Diagnostic::error(E_UNRESOLVED_FN, format!("cannot find function `{}`", name))
+                .with_label(Label::primary(*span, "not found in this scope")));
+        }
+        coerce_args(ev, &name, param, &mut typs, 0, *span)?;
+        let (typ, sym) = resolve_overload(ev, &name, &typs, *span)?;
+        *symbol = sym;
+        return Ok(typ);
+    }
+    unreachable!();
+}
+
+// methods are looked up on the receiver's struct, which is passed first
+fn typeof_method_call(ev: &mut Env, n: &mut AstNode) -> CheckResult<AstType> {
+    if let AstNode::MethodCall(ref mut obj, name, ref mut param, ref mut symbol, span) = n {
+        let otyp = typeof_value_expr(ev, obj)?;
+        if let AstType::Dyn(ref iface) = otyp {
+            let (slot, ret) = typeof_dyn_call(ev, iface, name, param, *span)?;
+            *n = AstNode::DynCall(obj.clone(), iface.clone(), slot, param.clone(), *span);
+            return Ok(ret);
+        }
+        let path = match otyp {
+            AstType::Ext(_) | AstType::Generic(_, _) => format!("{}::{}", otyp, name),
+            _ => {
+                return Err(Diagnostic::error(E_UNRESOLVED_FN, format!("no method named `{}` on primitive type `{}`", name, otyp))
+                    .with_label(Label::primary(obj.span(), format!("this is {}", otyp))));
+            }
+        };
+        let mut typs = vec![AstType::Ref(Box::new(otyp.clone()))];
+        typs.extend(typeof_args(ev, param)?);
+        // in a generic body, methods of a type parameter come from its bound
+        if let Some((tp, bound)) = ev.type_params.iter().find(|p| otyp == AstType::Ext(p.0.clone())).cloned() {
+            return typeof_bound_call(ev, &tp, &bound, name, &typs[1..], *span);
+        }
+        if !has_overloads(ev, &path) {
+            return Err(Diagnostic::error(E_UNRESOLVED_FN, format!("no method named `{}` found for `{}`", name, otyp))
+                .with_label(Label::primary(*span, format!("method not found in `{}`", otyp))));
+        }
+        coerce_args(ev, &path, param, &mut typs, 1, *span)?;
+        let (typ, sym) = resolve_overload(ev, &path, &typs, *span)?;
+        *symbol = sym;
+        return Ok(typ);
+    }
+    unreachable!();
+}
+
+fn typeof_enum_lit(ev: &mut Env, n: &mut AstNode) -> CheckResult<AstType> {
+    if let AstNode::EnumLit(ident, variant, ref mut args, ref mut tag, span) = n {
+        let (pos, typs) = resolve_variant(ev, &ident_name(ident), variant, *span)?;
+        if args.len() != typs.len() {
+            return Err(Diagnostic::error(E_MISMATCHED_TYPES, format!("variant `{}::{}` has {} field(s) but {} were supplied", ident_name(ident), variant, typs.len(), args.len()))
+                .with_label(Label::primary(*span, format!("expected {} field(s)", typs.len()))));
+        }
+        for (arg, ftyp) in args.iter_mut().zip(typs.iter()) {
+            let vtyp = typeof_value_expr(ev, arg)?;
+            if vtyp != *ftyp && !coerce(ev, ftyp, &vtyp, arg) {
+                return Err(mismatched_types(ftyp, &vtyp, arg.span()));
+            }
+        }
+        *tag = pos;
+        return Ok(ident_type(ident));
+    }
+    unreachable!();
+}
+
+// tag and payload types of `ename::variant`
+fn resolve_variant(ev: &Env, ename: &str, variant: &str, span: Span) -> CheckResult<(usize, Vec<AstType>)> {
+    let decl = match ev.enums.get(ename) {
+        Some(decl) => decl,
+        None => {
+            return Err(Diagnostic::error(E_UNKNOWN_TYPE, format!("cannot find enum `{}`", ename))
+                .with_label(Label::primary(span, "not an enum in this module")));
+        }
+    };
+    match decl.iter().position(|v| v.0 == variant) {
+        Some(pos) => Ok((pos, decl[pos].1.clone())),
+        None => Err(Diagnostic::error(E_NO_FIELD, format!("no variant named `{}` in enum `{}`", variant, ename))
+            .with_label(Label::primary(span, format!("variant not found in `{}`", ename)))),
+    }
+}
+
+// Arms are checked in order: a variant already covered, or any arm after a
+// catch-all, is unreachable, and every variant must be covered by some arm.
+// As a statement the arms are plain blocks; as a value they must agree on a type.
+fn typeof_match(ev: &mut Env, n: &mut AstNode, value: bool) -> CheckResult<AstType> {
+    if let AstNode::MatchExpr(ref mut val, ref mut arms, ref mut typ, _) = n {
+        let vtyp = typeof_value_expr(ev, val)?;
+        let ename = match vtyp {
+            AstType::Ext(ref name) if ev.enums.contains_key(name) => name.clone(),
+            _ => {
+                return Err(Diagnostic::error(E_MISMATCHED_TYPES, format!("cannot `match` on a value of type `{}`", vtyp))
+                    .with_label(Label::primary(val.span(), format!("this is {}", vtyp)))
+                    .with_note("only enums can be matched"));
+            }
+        };
+        let nvariants = ev.enums[&ename].len();
+        let mut covered = vec![false; nvariants];
+        let mut found: Option<(AstType, Span)> = None;
+        for (pat, body) in arms.iter_mut() {
+            let all = covered.iter().all(|c| *c);
+            ev.enter_scope();
+            let res = check_pattern(ev, pat, &vtyp);
+            ev.leave_scope();
+            let useful = match res? {
+                Some(tag) => !covered[tag],
+                None => !all,
+            };
+            if !useful {
+                ev.report(Diagnostic::warning(W_UNREACHABLE, "unreachable pattern")
+                    .with_label(Label::primary(pat.span(), "already covered by the arms above")));
+            }
+            match pat {
+                Pattern::Variant(_, _, _, tag, _) => covered[*tag] = true,
+                _ => covered.iter_mut().for_each(|c| *c = true),
+            }
+            ev.enter_scope();
+            bind_pattern(ev, pat, &vtyp);
+            if !value {
+                check_stmtblock(ev, body);
+                ev.leave_scope();
+                continue;
+            }
+            let arm = typeof_block_value(ev, body, pat.span());
+            ev.leave_scope();
+            match (arm?, &found) {
+                (Some((atyp, aspan)), Some((ftyp, fspan))) if atyp != *ftyp => {
+                    return Err(Diagnostic::error(E_MISMATCHED_TYPES, "`match` arms have incompatible types")
+                        .with_label(Label::primary(aspan, format!("expected {}, found {}", ftyp, atyp)))
+                        .with_label(Label::secondary(*fspan, format!("this is {}", ftyp))));
+                }
+                // an arm that returns or breaks takes the type of the others
+                (Some(arm), None) => found = Some(arm),
+                _ => (),
+            }
+        }
+        let missing: Vec<String> = ev.enums[&ename].iter().zip(covered.iter())
+            .filter(|(_, c)| !**c)
+            .map(|(v, _)| format!("`{}::{}`", ename, v.0))
+            .collect();
+        if !missing.is_empty() {
+            return Err(Diagnostic::error(E_NON_EXHAUSTIVE, format!("non-exhaustive patterns: {} not covered", missing.join(", ")))
+                .with_label(Label::primary(val.span(), format!("{} not covered", missing.join(", "))))
+                .with_note("add an arm for each missing variant, or a `_` arm"));
+        }
+        *typ = if value { found.map(|(t, _)| t).unwrap_or(AstType::Void) } else { AstType::Void };
+        return Ok(typ.clone());
+    }
+    unreachable!();
+}
+
+// resolves a variant pattern against the scrutinee's enum, returning its tag;
+// `None` for a catch-all
+fn check_pattern(ev: &mut Env, pat: &mut Pattern, vtyp: &AstType) -> CheckResult<Option<usize>> {
+    if let Pattern::Variant(ename, variant, subs, ref mut tag, span) = pat {
+        if AstType::Ext(ename.clone()) != *vtyp {
+            return Err(mismatched_types(vtyp, &AstType::Ext(ename.clone()), *span)
+                .with_note(format!("this pattern matches `{}`", ename)));
+        }
+        let (pos, typs) = resolve_variant(ev, ename, variant, *span)?;
+        if subs.len() != typs.len() {
+            return Err(Diagnostic::error(E_MISMATCHED_TYPES, format!("variant `{}::{}` has {} field(s) but the pattern has {}", ename, variant, typs.len(), subs.len()))
+                .with_label(Label::primary(*span, format!("expected {} field(s)", typs.len()))));
+        }
+        for sub in subs.iter() {
+            if let Pattern::Binding(name, bspan) = sub {
+                if ev.local_defined(name) {
+                    return Err(Diagnostic::error(E_REDEFINED, format!("identifier `{}` is bound more than once in the same pattern", name))
+                        .with_label(Label::primary(*bspan, "used in a pattern more than once")));
+                }
+                ev.local_def(name, AstType::Undef);
+            }
+        }
+        *tag = pos;
+        return Ok(Some(pos));
+    }
+    Ok(None)
+}
+
+// brings the bindings of a checked pattern into the arm's scope
+fn bind_pattern(ev: &mut Env, pat: &Pattern, vtyp: &AstType) {
+    match pat {
+        Pattern::Binding(name, _) => ev.local_def(name, vtyp.clone()),
+        Pattern::Variant(ename, _, subs, tag, _) => {
+            let typs = ev.enums[ename][*tag].1.clone();
+            for (sub, typ) in subs.iter().zip(typs) {
+                if let Pattern::Binding(name, _) = sub { ev.local_def(name, typ); }
+            }
+        }
+        Pattern::Wildcard(_) => (),
+    }
+}
+
+// the call has no symbol yet, each instance resolves it on its own
+fn typeof_bound_call(ev: &mut Env, tp: &str, bound: &Option<String>, name: &str, typs: &[AstType], span: Span) -> CheckResult<AstType> {
+    let sig = bound.as_ref()
+        .and_then(|iface| ev.interfaces[iface].iter().find(|m| m.0 == name))
+        .cloned();
+    let sig = match sig {
+        Some(sig) => sig,
+        None => {
+            let note = match bound {
+                Some(iface) => format!("`{}` is only known to implement `{}`", tp, iface),
+                None => format!("`{}` has no bound, so it has no methods", tp),
+            };
+            return Err(Diagnostic::error(E_UNRESOLVED_FN, format!("no method named `{}` found for type parameter `{}`", name, tp))
+                .with_label(Label::primary(span, format!("method not found in `{}`", tp)))
+                .with_note(note));
+        }
+    };
+    if sig.1 != typs {
+        let args: Vec<String> = typs.iter().map(|t| t.to_string()).collect();
+        return Err(Diagnostic::error(E_UNRESOLVED_FN, format!("no overload of `{}` takes ({})", name, args.join(", ")))
+            .with_label(Label::primary(span, format!("called with ({})", args.join(", "))))
+            .with_note(format!("candidate: {}", render_sig(name, true, &sig.1, &sig.2))));
+    }
+    Ok(sig.2)
+}
+
+// slot of the method in the interface's vtable and its return type
+fn typeof_dyn_call(ev: &mut Env, iface: &str, name: &str, args: &mut [AstNode], span: Span) -> CheckResult<(usize, AstType)> {
+    let (slot, sig) = match ev.interfaces[iface].iter().position(|m| m.0 == name) {
+        Some(slot) => (slot, ev.interfaces[iface][slot].clone()),
+        None => {
+            return Err(Diagnostic::error(E_UNRESOLVED_FN, format!("no method named `{}` found for `dyn {}`", name, iface))
+                .with_label(Label::primary(span, format!("method not found in `dyn {}`", iface)))
+                .with_note(format!("only the methods of `{}` can be called through `dyn {}`", iface, iface)));
+        }
+    };
+    let typs = typeof_args(ev, args)?;
+    let fits = sig.1.len() == typs.len() && sig.1.iter().zip(typs.iter()).all(|(p, t)| p == t || coercible(ev, p, t));
+    if !fits {
+        let found: Vec<String> = typs.iter().map(|t| t.to_string()).collect();
+        return Err(Diagnostic::error(E_UNRESOLVED_FN, format!("no overload of `{}` takes ({})", name, found.join(", ")))
+            .with_label(Label::primary(span, format!("called with ({})", found.join(", "))))
+            .with_note(format!("candidate: {}", render_sig(name, true, &sig.1, &sig.2))));
+    }
+    for (i, arg) in args.iter_mut().enumerate() {
+        coerce(ev, &sig.1[i], &typs[i], arg);
+    }
+    Ok((slot, sig.2))
+}
+
+fn has_overloads(ev: &Env, name: &str) -> bool {
+    let prefix = format!("{}:", name);
+    ev.symbols.keys().any(|key| key.starts_with(&prefix)) || ev.generics.contains_key(name)
+}
+
+// return type and symbol of the overload taking exactly `typs`, or of an
+// instance of a generic function that accepts them
+fn resolve_overload(ev: &mut Env, name: &str, typs: &[AstType], span: Span) -> CheckResult<(AstType, String)> {
+    let proto = prototype_fn(name, typs);
+    if let Some(typ) = ev.global_resolve(&proto).cloned() {
+        return Ok((typ, ev.symbols[&proto].clone()));
+    }
+    if let Some(found) = ev.instantiated.get(&proto) {
+        return Ok(found.clone());
+    }
+    let fits: Vec<AstNode> = ev.generics.get(name).into_iter().flatten()
+        .filter(|generic| infer_type_args(generic, typs).is_some())
+        .cloned().collect();
+    if fits.len() > 1 {
+        let args: Vec<String> = typs.iter().map(|t| t.to_string()).collect();
+        let mut diag = Diagnostic::error(E_AMBIGUOUS, format!("call to `{}` is ambiguous", name))
+            .with_label(Label::primary(span, format!("({}) fits {} generic overloads", args.join(", "), fits.len())));
+        for generic in fits.iter() {
+            diag = diag.with_note(format!("candidate: {}", render_generic_fn(name, generic)));
+        }
+        return Err(diag);
+    }
+    if let Some(generic) = fits.first() {
+        if let Some(found) = instantiate(ev, generic, typs, span)? {
+            return Ok(found);
+        }
+    }
+    let args: Vec<String> = typs.iter().map(|t| t.to_string()).collect();
+    let prefix = format!("{}:", name);
+    let mut candidates: Vec<String> = ev.symbols.keys()
+        .filter(|key| key.starts_with(&prefix))
+        .map(|key| format!("{}({})", name, key[prefix.len()..].trim_start_matches('-').replace("-", ", ")))
+        .collect();
+    for generic in ev.generics.get(name).into_iter().flatten() {
+        candidates.push(render_generic_fn(name, generic));
+    }
+    candidates.sort();
+    let mut diag = Diagnostic::error(E_UNRESOLVED_FN, format!("no overload of `{}` takes ({})", name, args.join(", ")))
+        .with_label(Label::primary(span, format!("called with ({})", args.join(", "))));
+    for candidate in candidates {
+        diag = diag.with_note(format!("candidate: {}", candidate));
+    }
+    Err(diag)
+}
+
+fn render_generic_fn(name: &str, generic: &AstNode) -> String {
+    if let AstNode::FnDecl(_, generics, param, _, _, _, _) = generic {
+        let params: Vec<String> = param.iter().map(|p| ident_type(p).to_string()).collect();
+        return format!("{}({})", render_generic(name, generics), params.join(", "));
+    }
+    unreachable!();
+}
+
+// the type arguments that make the parameters of `generic` take `typs`
+fn infer_type_args(generic: &AstNode, typs: &[AstType]) -> Option<HashMap<String, AstType>> {
+    if let AstNode::FnDecl(_, generics, param, _, _, _, _) = generic {
+        if param.len() != typs.len() { return None; }
+        let mut map = HashMap::new();
+        for (p, typ) in param.iter().zip(typs) {
+            if !unify(&ident_type(p), typ, generics, &mut map) { return None; }
+        }
+        return Some(map);
+    }
+    unreachable!();
+}
+
+// Infers the type arguments of `generic` from the argument types and checks
+// the bounds. `None` when the arguments do not fit its parameters. Concrete
+// calls queue an instance with the types substituted, named like an overload.
+fn instantiate(ev: &mut Env, generic: &AstNode, typs: &[AstType], span: Span) -> CheckResult<Option<(AstType, String)>> {
+    if let AstNode::FnDecl(ident, generics, _, _, _, _, _) = generic {
+        let map = match infer_type_args(generic, typs) {
+            Some(map) => map,
+            None => return Ok(None),
+        };
+        let name = ident_name(ident);
+        for (tp, bound) in generics.iter() {
+            let arg = &map[tp];
+            if let Some(iface) = bound {
+                if !implements(ev, arg, iface) {
+                    return Err(Diagnostic::error(E_UNSATISFIED_BOUND, format!("the bound `{}: {}` is not satisfied", tp, iface))
+                        .with_label(Label::primary(span, format!("`{}` does not implement `{}`", arg, iface)))
+                        .with_note(format!("required by a bound on `{}`", name)));
+                }
+            }
+        }
+        let mut ret = ident_type(ident);
+        subst_type(&mut ret, &map);
+        // the caller may use a struct that only this instance names
+        check_type(ev, &ret, span)?;
+        // called from a generic body with its own type parameters
+        if map.values().any(|t| ev.type_params.iter().any(|p| mentions_type(t, &p.0))) {
+            return Ok(Some((ret, String::new())));
+        }
+        if ev.instance_depth >= INSTANCE_DEPTH_LIMIT {
+            return Err(Diagnostic::error(E_RECURSION_LIMIT, format!("reached the instantiation depth limit of {} for `{}`", INSTANCE_DEPTH_LIMIT, name))
+                .with_label(Label::primary(span, format!("instantiates `{}` once more", name)))
+                .with_note(format!("`{}` is instantiated with ever larger types, check recursive calls with a generic argument", name)));
+        }
+        let symbol = mangle(&name, typs);
+        let mut inst = generic.clone();
+        subst_types(&mut inst, &map);
+        if let AstNode::FnDecl(_, ref mut generics, _, _, _, ref mut link, _) = inst {
+            generics.clear();
+            link.symbol = symbol.clone();
+        }
+        ev.instances.push((inst, span, ev.instance_depth + 1));
+        ev.instantiated.insert(prototype_fn(&name, typs), (ret.clone(), symbol.clone()));
+        return Ok(Some((ret, symbol)));
+    }
+    unreachable!();
+}
+
+// binds the type parameters in `param` so that it equals `arg`
+fn unify(param: &AstType, arg: &AstType, generics: &Generics, map: &mut HashMap<String, AstType>) -> bool {
+    match (param, arg) {
+        (AstType::Ext(name), _) if generics.iter().any(|g| g.0 == *name) => {
+            match map.get(name) {
+                Some(bound) => bound == arg,
+                None => { map.insert(name.clone(), arg.clone()); true }
+            }
+        }
+        (AstType::Ref(p), AstType::Ref(a)) => unify(p, a, generics, map),
+        (AstType::Generic(pbase, pargs), AstType::Generic(abase, aargs)) => {
+            pbase == abase && pargs.len() == aargs.len() &&
+                pargs.iter().zip(aargs.iter()).all(|(p, a)| unify(p, a, generics, map))
+        }
+        _ => param == arg,
+    }
+}
+
+fn is_type_param(ev: &Env, typ: &AstType) -> bool {
+    ev.type_params.iter().any(|p| *typ == AstType::Ext(p.0.clone()))
+}
+
+fn implements(ev: &Env, typ: &AstType, iface: &str) -> bool {
+    match typ {
+        AstType::Ext(name) => {
+            ev.impls.contains(&(name.clone(), iface.to_string())) ||
+                ev.type_params.iter().any(|p| p.0 == *name && p.1.as_deref() == Some(iface))
+        }
+        AstType::Dyn(name) => name == iface,
+        _ => false,
+    }
+}
+
+fn typeof_binary_op(ev: &mut Env, n: &mut AstNode) -> CheckResult<AstType> {
+    if let AstNode::BinaryOp(ref mut lhs, op, ref mut rhs, ref mut typ, n_span) = n {
+        let n_span = *n_span;
+        let ltyp = typeof_value_expr(ev, lhs)?;
+        let rtyp = typeof_value_expr(ev, rhs)?;
+        if *op == Operator::AND || *op == Operator::OR {
+            for &(typ, span) in [(&ltyp, lhs.span()), (&rtyp, rhs.span())].iter() {
+                if *typ != AstType::Bool {
+                    return Err(mismatched_types(&AstType::Bool, typ, span)
+                        .with_note(format!("both operands of `{}` must be bool", op)));
+                }
+            }
+        }
+        if rtyp != ltyp {
+            return Err(mismatched_types(&ltyp, &rtyp, rhs.span())
+                .with_label(Label::secondary(lhs.span(), format!("this is {}", ltyp)))
+                .with_note(format!("both operands of `{}` must have the same type", op)));
+        }
+        let ok = match ltyp {
+            AstType::Int | AstType::Float => is_math_op(*op) || is_compare_op(*op),
+            // checked again with the type argument of each instance
+            _ if is_type_param(ev, &ltyp) => is_math_op(*op) || is_compare_op(*op),
+            AstType::Bool => is_compare_op(*op) || *op == Operator::AND || *op == Operator::OR,
+            _ => false,
+        };
+        if !ok {
+            return Err(Diagnostic::error(E_MISMATCHED_TYPES, format!("cannot apply binary operator `{}` to type `{}`", op, ltyp))
+                .with_label(Label::primary(n_span, format!("`{}` is not defined for {}", op, ltyp))));
+        }
+        *typ = if is_logic_op(*op) { AstType::Bool } else { rtyp };
+        return Ok(typ.clone());
+    }
+    unreachable!();
+}
+
+fn typeof_unary_op(ev: &mut Env, n: &mut AstNode) -> CheckResult<AstType> {
+    if let AstNode::UnaryOp(op, ref mut val, ref mut typ, span) = n {
+        let vtyp = match (&op, &**val) {
+            // the literal of `-2147483648` is out of range on its own
+            (Operator::SUB, AstNode::Int(_, _)) => AstType::Int,
+            _ => typeof_value_expr(ev, val)?,
+        };
+        let ok = match op {
+            Operator::SUB => vtyp == AstType::Int || vtyp == AstType::Float || is_type_param(ev, &vtyp),
+            Operator::NOT => vtyp == AstType::Bool,
+            _ => unreachable!("[typeof_unary_op] {}", op),
+        };
+        if !ok {
+            return Err(Diagnostic::error(E_MISMATCHED_TYPES, format!("cannot apply unary operator `{}` to type `{}`", op, vtyp))
+                .with_label(Label::primary(*span, format!("`{}` is not defined for {}", op, vtyp))));
+        }
+        *typ = vtyp.clone();
+        return Ok(vtyp);
+    }
+    unreachable!();
+}
+
+// `if` in value position: both branches must end in a value of the same type
+fn typeof_if_expr(ev: &mut Env, n: &mut AstNode) -> CheckResult<AstType> {
+    if let AstNode::IfStmt(ref mut cond, ref mut tblock, ref mut fblock, ref mut typ, span) = n {
+        typeof_bool_expr(ev, cond)?;
+        let tval = typeof_block_value(ev, tblock, *span)?;
+        if fblock.is_empty() {
+            let expected = tval.map(|(t, _)| t).unwrap_or(AstType::Void);
+            return Err(Diagnostic::error(E_MISMATCHED_TYPES, "`if` may be missing an `else` clause")
+                .with_label(Label::primary(*span, format!("expected {}, found void", expected)))
+                .with_note("`if` expressions without `else` have no value"));
+        }
+        let fval = typeof_block_value(ev, fblock, *span)?;
+        *typ = match (tval, fval) {
+            (Some((ttyp, tspan)), Some((ftyp, fspan))) => {
+                if ttyp != ftyp {
+                    return Err(Diagnostic::error(E_MISMATCHED_TYPES, "`if` and `else` have incompatible types")
+                        .with_label(Label::primary(fspan, format!("expected {}, found {}", ttyp, ftyp)))
+                        .with_label(Label::secondary(tspan, format!("this is {}", ttyp))));
+                }
+                ttyp
+            }
+            // a branch that returns or breaks takes the type of the other one
+            (Some((t, _)), None) | (None, Some((t, _))) => t,
+            (None, None) => AstType::Void,
+        };
+        return Ok(typ.clone());
+    }
+    unreachable!();
+}
+
+// type and span of the value a block ends with, `None` when it always diverges
+fn typeof_block_value(ev: &mut Env, block: &mut Vec<AstNode>, span: Span) -> CheckResult<Option<(AstType, Span)>> {
+    let last = match block.pop() {
+        Some(last) => last,
+        None => return Ok(Some((AstType::Void, span))),
+    };
+    ev.enter_scope();
+    check_stmtblock(ev, block);
+    let mut last = last;
+    let res = match last {
+        AstNode::TailExpr(ref mut expr, span) => typeof_value_expr(ev, expr).map(|t| Some((t, span))),
+        AstNode::IfStmt(_, _, _, _, span) => typeof_if_expr(ev, &mut last).map(|t| Some((t, span))),
+        AstNode::MatchExpr(_, _, _, span) => typeof_match(ev, &mut last, true).map(|t| Some((t, span))),
+        AstNode::ReturnStmt(_, _, _) | AstNode::Break(_, _) | AstNode::Continue(_, _) => {
+            check_expr(ev, &mut last).map(|_| None)
+        }
+        _ => check_expr(ev, &mut last).map(|_| Some((AstType::Void, last.span()))),
+    };
+    ev.leave_scope();
+    block.push(last);
+    res
+}
+
+fn unresolved_name(name: &str, span: Span) -> Diagnostic {
+    Diagnostic::error(E_UNRESOLVED_NAME, format!("cannot find value `{}` in this scope", name))
+        .with_label(Label::primary(span, "not found in this scope"))
+}
+
+// arguments of a call, which must all have a value
+fn typeof_args(ev: &mut Env, args: &mut [AstNode]) -> CheckResult<Vec<AstType>> {
+    let mut typs = Vec::new();
+    for arg in args.iter_mut() {
+        match typeof_value_expr(ev, arg)? {
+            AstType::Void | AstType::Nil => return Err(void_value(arg.span())),
+            typ => typs.push(typ),
+        }
+    }
+    Ok(typs)
+}
+
+fn void_value(span: Span) -> Diagnostic {
+    Diagnostic::error(E_MISMATCHED_TYPES, "expression has no value")
+        .with_label(Label::primary(span, "this expression has type void"))
+}
+
+fn mismatched_types(expected: &AstType, found: &AstType, span: Span) -> Diagnostic {
+    Diagnostic::error(E_MISMATCHED_TYPES, "mismatched types")
+        .with_label(Label::primary(span, format!("expected {}, found {}", expected, found)))
+}
+
+// a struct converts to `dyn Interface` where one is expected if it implements it
+fn coercible(ev: &Env, expected: &AstType, found: &AstType) -> bool {
+    match (expected, found) {
+        (AstType::Dyn(iface), AstType::Ext(tname)) => ev.impls.contains(&(tname.clone(), iface.clone())),
+        _ => false,
+    }
+}
+
+// wraps `n` in the conversion, see `coercible`
+fn coerce(ev: &Env, expected: &AstType, found: &AstType, n: &mut AstNode) -> bool {
+    if !coercible(ev, expected, found) { return false; }
+    if let (AstType::Dyn(iface), AstType::Ext(tname)) = (expected, found) {
+        let span = n.span();
+        *n = AstNode::DynCast(Box::new(n.clone()), tname.clone(), iface.clone(), span);
+    }
+    true
+}
+
+// Without an overload taking `typs` exactly, the only one that accepts the
+// arguments after converting some to `dyn` is picked, and several are an
+// error. The first `skip` types are not arguments in `args`, e.g. the
+// receiver of a method.
+fn coerce_args(ev: &Env, name: &str, args: &mut [AstNode], typs: &mut [AstType], skip: usize, span: Span) -> CheckResult<()> {
+    if ev.symbols.contains_key(&prototype_fn(name, typs)) { return Ok(()); }
+    let fits: Vec<&Vec<AstType>> = ev.overloads.get(name).into_iter().flatten()
+        .filter(|params| params.len() == typs.len() &&
+                params.iter().zip(typs.iter()).all(|(p, t)| p == t || coercible(ev, p, t)))
+        .collect();
+    if fits.len() > 1 {
+        let args: Vec<String> = typs.iter().map(|t| t.to_string()).collect();
+        let mut diag = Diagnostic::error(E_AMBIGUOUS, format!("call to `{}` is ambiguous", name))
+            .with_label(Label::primary(span, format!("({}) converts to the parameters of {} overloads", args.join(", "), fits.len())));
+        for params in fits {
+            let params: Vec<String> = params.iter().map(|t| t.to_string()).collect();
+            diag = diag.with_note(format!("candidate: {}({})", name, params.join(", ")));
+        }
+        return Err(diag);
+    }
+    if fits.len() != 1 { return Ok(()); }
+    let params = fits[0].clone();
+    for (i, p) in params.iter().enumerate().skip(skip) {
+        if coerce(ev, p, &typs[i].clone(), &mut args[i - skip]) {
+            typs[i] = p.clone();
+        }
+    }
+    Ok(())
+}
+
+fn is_math_op(op: Operator) -> bool {
+    match op {
+        Operator::PLUS | Operator::SUB | Operator::MUL |
+        Operator::DIV | Operator::REM => true,
+        _ => false,
+    }
+}
+
+fn define_local_var(ev: &mut Env, p: &Vec<AstNode>) {
+    for var in p {
+        match var {
+            // `self` reads as the struct it points to
+            AstNode::Ident(name, AstType::Ref(typ), _) => ev.local_def(&name, (**typ).clone()),
+            AstNode::Ident(name, typ, _) => ev.local_def(&name, typ.clone()),
+            _ => (),
+        }
+    }
+}
+
+fn typeof_param(ev: &mut Env, n: AstNode) -> CheckResult<AstType> {
+    let typ = ident_type(&n);
+    check_type(ev, &typ, n.span())?;
+    Ok(typ)
+}
+
+// declared parameter types of a function
+fn typeof_params(ev: &mut Env, p: &mut Vec<AstNode>) -> CheckResult<Vec<AstType>> {
+    let mut typs = Vec::new();
+    for item in p {
+        typs.push(typeof_param(ev, item.clone())?);
+    }
+    Ok(typs)
+}
+
+// parses and checks `sources`, which must be free of syntax errors
+#[cfg(test)]
+fn check(sources: &str) -> (Vec<AstNode>, Vec<Diagnostic>) {
+    use crate::grammar::ModuleParser;
+    use crate::span::FileId;
+    semantic_check(ModuleParser::new().parse(FileId(0), sources).unwrap())
+}
+
+// codes of the diagnostics reported on `sources`, in order
+#[cfg(test)]
+fn check_src(sources: &str) -> Vec<&'static str> {
+    check(sources).1.iter().map(|d| d.code).collect()
+}
+
+#[test]
+fn span_test() {
+    use crate::grammar::ModuleParser;
+    use crate::span::FileId;
+    let sources = r#"
+        fn foo(a: int) -> int {
+            let b = a + 1;
+            return b * 2;
+        }
+        enum Shape { Circle(float), Rect(int, Pair<int>) }
+    "#;
+    let stmts = ModuleParser::new().parse(FileId(0), sources).unwrap();
+    let text = |span: Span| &sources[span.start..span.end];
+    if let AstNode::FnDecl(ident, _, param, rspan, body, _, span) = &stmts[0] {
+        assert!(text(*span).starts_with("fn foo(a: int)") && text(*span).ends_with('}'));
+        assert_eq!(text(ident.span()), "foo");
+        assert_eq!(text(param[0].span()), "a: int");
+        assert_eq!(text(*rspan), "int");
+        assert_eq!(text(body[0].span()), "let b = a + 1;");
+        if let AstNode::VarDecl(var, val, _, _) = &body[0] {
+            assert_eq!(text(var.span()), "b");
+            assert_eq!(text(val.span()), "a + 1");
+        }
+        assert_eq!(text(body[1].span()), "return b * 2");
+    } else {
+        panic!("expected a function, found {:?}", stmts[0]);
+    }
+    if let AstNode::EnumDecl(_, variants, _) = &stmts[1] {
+        assert_eq!(text(variants[1].1[1].1), "Pair<int>");
+    }
+
+    // type errors point at the annotation
+    let errors = r#"
+        interface I { fn get(self) -> Pointer; }
+        fn bar() -> float {
+            return 1;
+        }
+        enum E { A(Pointer) }
+    "#;
+    let (_, diags) = check(errors);
+    let spans: Vec<&str> = diags.iter().flat_map(|d| d.labels.iter().map(|l| &errors[l.span.start..l.span.end])).collect();
+    assert_eq!(spans, vec!["Pointer", "Pointer", "1", "float"]);
+}
+
+#[test]
+fn semantic_errors_test() {
+    use crate::grammar::ModuleParser;
+    use crate::span::FileId;
+    let sources = r#"
+        fn foo(a: int) -> int {
+            let b = a + 1.5;
+            c = a;
+            return a;
+        }
+        fn foo(a: int) -> int { return a; }
+    "#;
+    assert_eq!(check_src(sources), vec![E_REDEFINED, E_MISMATCHED_TYPES, E_UNRESOLVED_NAME]);
+
+    // a binding is not in scope in its own initializer, and its uses are not
+    // reported again
+    let sources = r#"
+        fn foo() -> int {
+            let x = x;
+            let y = x + 1;
+            return y;
+        }
+        fn bar() -> int {
+            let x = 1;
+            if x > 0 { let x = x + 1; return x; }
+            return x;
+        }
+    "#;
+    assert_eq!(check_src(sources), vec![E_UNRESOLVED_NAME]);
+
+    // functions are only declared at the top level
+    let nested = "fn main() -> int { fn inner() -> int { return 1; } return inner(); }";
+    assert!(ModuleParser::new().parse(FileId(0), nested).is_err());
+}
+
+#[test]
+fn logic_operand_test() {
+    let sources = r#"
+        fn foo(a: int) -> int {
+            if a && a > 1 { return 1; }
+            return 0;
+        }
+    "#;
+    let (_, diags) = check(sources);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].labels[0].message, "expected bool, found int");
+}
+
+#[test]
+fn int_literal_test() {
+    use crate::grammar::ModuleParser;
+    use crate::span::FileId;
+    let sources = r#"
+        fn foo(x: int) -> int {
+            let min = -2147483648;
+            let f = -2147483648.5;
+            let g = x - -2147483648;
+            return x-2147483648;
+        }
+    "#;
+    assert_eq!(check_src(sources), vec![E_LITERAL_RANGE]);
+    let (_, diags) = check(sources);
+    assert_eq!(diags[0].labels[0].message, "`2147483648` does not fit in an int literal");
+
+    assert!(ModuleParser::new().parse(FileId(0), "let a = -2147483649;").is_err());
+}
+
+#[test]
+fn loop_control_test() {
+    let sources = r#"
+        fn foo(a: int) {
+            if a > 1 { break; }
+            while a > 1 {
+                continue;
+                a = a - 1;
+            }
+        }
+    "#;
+    assert_eq!(check_src(sources), vec![E_OUTSIDE_LOOP, W_UNREACHABLE]);
+    let (_, diags) = check(sources);
+    assert!(!diags[1].is_error());
+}
+
+#[test]
+fn loop_label_test() {
+    let sources = r#"
+        fn foo(a: int) {
+            'outer: loop {
+                for i in 0..a { if i > 2 { break 'inner; } }
+                break 'outer;
+            }
+            for i in 0..1.5 {}
+            'a: loop {
+                'a: while a > 0 { break 'a; }
+                break;
+            }
+            for i in 0..10 step 0 {}
+            for i in 10..0 step -1 {}
+            for i in 0..10 step a - 1 {}
+        }
+    "#;
+    assert_eq!(check_src(sources), vec![E_UNDECLARED_LABEL, E_MISMATCHED_TYPES, E_REDEFINED, E_RANGE_STEP, E_RANGE_STEP]);
+    let (_, diags) = check(sources);
+    assert_eq!(diags[2].message, "label `'a` shadows a label of an enclosing loop");
+    assert_eq!(&sources[diags[2].labels[0].span.start..diags[2].labels[0].span.end], "'a");
+    assert_eq!(diags[4].labels[0].message, "the step is -1");
+}
+
+#[test]
+fn if_expr_test() {
+    let sources = r#"
+        fn foo(a: int) -> int {
+            let b = if a > 1 { 1 } else { 2.5 };
+            let c = if a > 1 { 1 };
+            let d = if a > 1 { 1 } else if a > 0 { 2 } else { 3 };
+            return d;
+        }
+    "#;
+    let (_, diags) = check(sources);
+    let msgs: Vec<&str> = diags.iter().map(|d| &d.message[..]).collect();
+    assert_eq!(msgs, vec!["`if` and `else` have incompatible types", "`if` may be missing an `else` clause"]);
+}
+
+#[test]
+fn block_scope_test() {
+    let sources = r#"
+        fn foo(a: int) -> int {
+            if a > 1 { let val = 1; }
+            else { let val = 2.5; }
+            while a > 0 {
+                let a = 1.5;
+                let a = 2;
+            }
+            let b = val;
+            return a;
+        }
+    "#;
+    assert_eq!(check_src(sources), vec![E_REDEFINED, E_UNRESOLVED_NAME]);
+}
+
+#[test]
+fn return_check_test() {
+    let sources = r#"
+        fn a(n: int) -> int {
+            if n > 1 { return 1; }
+        }
+        fn b(n: int) -> int {
+            if n > 1 { return 1; } else if n > 0 { return 2; } else { return 3; }
+        }
+        fn c(n: int) -> int {
+            while true {
+                if n > 1 { return n; }
+            }
+        }
+        fn d(n: int) -> float {
+            return n;
+        }
+        fn e(n: int) {
+            return n;
+        }
+        fn f(n: int) -> int {
+            'outer: loop {
+                loop { break 'outer; }
+            }
+        }
+    "#;
+    assert_eq!(check_src(sources), vec![E_MISSING_RETURN, E_MISMATCHED_TYPES, E_MISMATCHED_TYPES, E_MISSING_RETURN]);
+    let (_, diags) = check(sources);
+    assert_eq!(diags[2].labels[0].message, "expected void, found int");
+}
+
+#[test]
+fn definite_init_test() {
+    let sources = r#"
+        fn foo(a: int) -> int {
+            let b: int;
+            let c: int;
+            let d: int;
+            let e: int;
+            if a > 1 { b = 1; c = 1; } else { c = 2; }
+            while a > 0 { d = 1; a = a - 1; }
+            loop {
+                if a > 5 { e = 1; break; }
+                e = 2;
+                break;
+            }
+            return b + c + d + e + b;
+        }
+    "#;
+    assert_eq!(check_src(sources), vec![E_UNINITIALIZED, E_UNINITIALIZED]);
+    let (_, diags) = check(sources);
+    assert_eq!(diags[0].message, "used binding `b` is possibly-uninitialized");
+    assert_eq!(diags[1].message, "used binding `d` is possibly-uninitialized");
+    assert_eq!(diags[1].labels[1].message, "binding declared here but left uninitialized");
+
+    let sources = r#"
+        enum S { A(int), B }
+        fn bar() -> int {
+            let x: int;
+            let y = match S::A(1) { S::A(x) => x, S::B => 0 };
+            return y + x;
+        }
+    "#;
+    assert_eq!(check_src(sources), vec![E_UNINITIALIZED]);
+    let (_, diags) = check(sources);
+    assert_eq!(diags[0].labels[0].span.start, sources.find("y + x").unwrap() + 4);
+}
+
+#[test]
+fn overload_test() {
+    let sources = r#"
+        #[no_mangle]
+        fn foo(a: int) -> int { return a; }
+        #[no_mangle]
+        fn foo(a: float) -> float { return a; }
+        fn bar(a: int, b: int) -> int { return foo(a) + foo(1.5, b); }
+    "#;
+    assert_eq!(check_src(sources), vec![E_AMBIGUOUS, E_UNRESOLVED_FN]);
+    let (ast, diags) = check(sources);
+    assert_eq!(diags[1].message, "no overload of `foo` takes (float, int)");
+    assert_eq!(diags[1].notes, vec!["candidate: foo(int)"]);
+    if let AstNode::FnDecl(_, _, _, _, _, link, _) = &ast[2] {
+        assert_eq!(link.symbol, "_D3barii");
+    }
+
+    let sources = r#"
+        fn pick<T>(a: T, b: int) -> T { return a; }
+        fn pick<T>(a: int, b: T) -> T { return b; }
+        fn baz() -> int {
+            let t = pick(true, 3);
+            return pick(1, 2);
+        }
+    "#;
+    assert_eq!(check_src(sources), vec![E_AMBIGUOUS]);
+    let (_, diags) = check(sources);
+    assert_eq!(diags[0].message, "call to `pick` is ambiguous");
+    assert_eq!(diags[0].labels[0].message, "(int, int) fits 2 generic overloads");
+    assert_eq!(diags[0].notes, vec!["candidate: pick<T>(T, int)", "candidate: pick<T>(int, T)"]);
+}
+
+#[test]
+fn const_test() {
+    let sources = r#"
+        const A: int = 7 / (3 - 3);
+        const B: int = 1.5;
+        let c = foo();
+        const D: int = 2147483647 * 2147483647 * 3;
+        const E: int = -4 * 2;
+        const F: int = 2147483647 * 2147483647 * 2;
+        fn foo() -> int {
+            E = 1;
+            return E;
+        }
+    "#;
+    assert_eq!(check_src(sources), vec![E_NOT_CONST, E_MISMATCHED_TYPES, E_NOT_CONST, E_NOT_CONST, E_ASSIGN_CONST]);
+    let (ast, diags) = check(sources);
+    assert_eq!(diags[0].labels[0].message, "attempt to divide by zero");
+    if let AstNode::ConstDecl(_, val, _, _) = &ast[4] {
+        assert!(match **val { AstNode::Int(-8, _) => true, _ => false });
+    }
+    // folded as i64, the width codegen emits `int` at
+    if let AstNode::ConstDecl(_, val, _, _) = &ast[5] {
+        assert!(match **val { AstNode::Int(9223372028264841218, _) => true, _ => false });
+    }
+
+    let sources = r#"
+        let a = B + 1;
+        const B: int = 1;
+        let x = y * 2;
+        let y = x;
+        const S: int = S;
+        fn get() -> int { return a + x; }
+    "#;
+    assert_eq!(check_src(sources), vec![E_CYCLE, E_CYCLE]);
+    let (ast, diags) = check(sources);
+    assert_eq!(diags[0].message, "cycle detected when checking the initializer of `x`");
+    assert_eq!(diags[0].labels[1].message, "which needs `x`");
+    assert_eq!(diags[1].labels[0].message, "its initializer needs `S`");
+    if let AstNode::VarDecl(_, val, _, _) = &ast[0] {
+        assert!(match **val { AstNode::Int(2, _) => true, _ => false });
+    }
+}
+
+#[test]
+fn struct_check_test() {
+    let sources = r#"
+        struct Point { x: int, y: int }
+        fn foo(a: int) -> int {
+            let p = Point { x: 1 };
+            let q = Point { x: 1, y: 2.5 };
+            let r = Point { x: 1, y: 2, z: 3 };
+            let s = Point { x: 1, y: 2 };
+            s.x = true;
+            foo(1).x = 2;
+            return s.z + a.x;
+        }
+    "#;
+    let (_, diags) = check(sources);
+    let msgs: Vec<&str> = diags.iter().map(|d| &d.message[..]).collect();
+    assert_eq!(msgs, vec![
+        "missing field `y` in initializer of `Point`",
+        "mismatched types",
+        "struct `Point` has no field named `z`",
+        "mismatched types",
+        "invalid left-hand side of assignment",
+        "no field `z` on type `Point`",
+    ]);
+
+    let sources = r#"
+        struct Node { v: int, next: Node }
+        struct A { b: B }
+        struct B { a: A }
+        struct Cell<T> { v: T, next: Cell<T> }
+        struct Wrap { node: Node }
+        fn f(c: Cell<int>) -> int { return c.v; }
+    "#;
+    let (_, diags) = check(sources);
+    let msgs: Vec<&str> = diags.iter().map(|d| &d.message[..]).collect();
+    assert_eq!(msgs, vec![
+        "recursive type `Node` has infinite size",
+        "recursive type `A` has infinite size",
+        "recursive type `B` has infinite size",
+        "recursive type `Cell` has infinite size",
+    ]);
+}
+
+#[test]
+fn type_name_test() {
+    let sources = r#"
+        struct Point { x: int, next: Node }
+        struct point { y: Int }
+        fn foo(p: Point, q: point) -> Pointer {
+            let r: Point;
+            let s: POINT;
+            return p;
+        }
+    "#;
+    let (_, diags) = check(sources);
+    let msgs: Vec<&str> = diags.iter().map(|d| &d.message[..]).collect();
+    // `return p` is not checked against the unknown return type
+    assert_eq!(msgs, vec!["unknown type `Node`", "unknown type `Int`", "unknown type `Pointer`", "unknown type `POINT`"]);
+    let span = diags[2].labels[0].span;
+    assert_eq!(&sources[span.start..span.end], "Pointer");
+}
+
+#[test]
+fn method_check_test() {
+    let sources = r#"
+        struct Point { x: int, y: int }
+        impl Point {
+            fn len(self) -> int { return self.x; }
+            fn len(self) -> int { return self.y; }
+        }
+        impl Pt { fn new() {} }
+        fn free(self) {}
+        fn main() -> int {
+            let p = Point { x: 1, y: 2 };
+            let a = 3;
+            a.len();
+            p.area();
+            p.len(1);
+            Point::len(p);
+            return self.x;
+        }
+    "#;
+    let (_, diags) = check(sources);
+    let msgs: Vec<&str> = diags.iter().map(|d| &d.message[..]).collect();
+    assert_eq!(msgs, vec![
+        "function `Point::len` is defined multiple times",
+        "unknown type `Pt`",
+        "`self` parameter is only allowed in an `impl` block",
+        "no method named `len` on primitive type `int`",
+        "no method named `area` found for `Point`",
+        "no overload of `Point::len` takes (&Point, int)",
+        "no overload of `Point::len` takes (Point)",
+        "cannot find value `self` in this scope",
+    ]);
+}
+
+#[test]
+fn match_check_test() {
+    let sources = r#"
+        enum Shape { Circle(int), Rect(int, int), Empty }
+        enum Dup { A, A }
+        struct Point { x: int }
+        fn foo(s: Shape, p: Point) -> int {
+            let a = match s {
+                Shape::Circle(r) => r,
+                Shape::Circle(_) => 1,
+            };
+            let b = match s {
+                Shape::Rect(w, h) => w * h,
+                _ => 2,
+                Shape::Empty => 3,
+            };
+            let c = match s { Shape::Rect(w) => w, _ => 0 };
+            let d = match s { Shape::Empty => 1, _ => true };
+            let e = match p { _ => 1 };
+            let f = Shape::Circle(1.5);
+            let g = Shape::Square;
+            let h = Shape { x: 1 };
+            return 0;
+        }
+    "#;
+    let (_, diags) = check(sources);
+    let msgs: Vec<&str> = diags.iter().map(|d| &d.message[..]).collect();
+    assert_eq!(msgs, vec![
+        "variant `Dup::A` is defined multiple times",
+        "unreachable pattern",
+        "non-exhaustive patterns: `Shape::Rect`, `Shape::Empty` not covered",
+        "unreachable pattern",
+        "variant `Shape::Rect` has 2 field(s) but the pattern has 1",
+        "`match` arms have incompatible types",
+        "cannot `match` on a value of type `Point`",
+        "mismatched types",
+        "no variant named `Square` in enum `Shape`",
+        "`Shape` is an enum, not a struct",
+    ]);
+
+    let sources = r#"
+        enum List { Cons(int, List), Empty }
+        enum Tree { Leaf(int), Node(Pair) }
+        struct Pair { l: Tree, r: Tree }
+        enum Opt { Some(Shape), None }
+        enum Shape { Circle(int), Empty }
+    "#;
+    let (_, diags) = check(sources);
+    let msgs: Vec<&str> = diags.iter().map(|d| &d.message[..]).collect();
+    assert_eq!(msgs, vec![
+        "recursive type `List` has infinite size",
+        "recursive type `Tree` has infinite size",
+        "recursive type `Pair` has infinite size",
+    ]);
+
+    let sources = r#"
+        enum S { A(int), B }
+        fn foo(s: S) -> int {
+            let x = match s { S::A(_) => { return 1; } S::B => { return 2; } };
+            return x;
+        }
+    "#;
+    let (_, diags) = check(sources);
+    let msgs: Vec<&str> = diags.iter().map(|d| &d.message[..]).collect();
+    assert_eq!(msgs, vec!["unreachable statement"]);
+}
+
+#[test]
+fn interface_check_test() {
+    let sources = r#"
+        interface Shape { fn area(self) -> int; fn name(self) -> int; }
+        struct Square { side: int }
+        struct Circle { r: int }
+        impl Shape for Square {
+            fn area(self) -> float { return 1.0; }
+            fn extra(self) {}
+        }
+        impl Drawable for Circle { fn draw(self) {} }
+        fn f<T: Shape>(s: T) -> int { return s.perimeter(); }
+        fn g<T: Shape>(s: T) -> int { return s.area(1); }
+        fn h<T: Shape>() {}
+        fn k<T: Shape>(s: T) -> int { return s.area(); }
+        fn main() -> int {
+            k(Circle { r: 1 });
+            k(1, 2);
+            return k(Square { side: 2 });
+        }
+    "#;
+    let (_, diags) = check(sources);
+    let msgs: Vec<&str> = diags.iter().map(|d| &d.message[..]).collect();
+    assert_eq!(msgs, vec![
+        "method `area` has an incompatible signature for interface `Shape`",
+        "method `extra` is not a member of interface `Shape`",
+        "not all methods of `Shape` are implemented for `Square`",
+        "cannot find interface `Drawable`",
+        "type parameter `T` is not used by any parameter",
+        "no method named `perimeter` found for type parameter `T`",
+        "no overload of `area` takes (int)",
+        "the bound `T: Shape` is not satisfied",
+        "no overload of `k` takes (int, int)",
+    ]);
+}
+
+#[test]
+fn dyn_check_test() {
+    let sources = r#"
+        interface Shape { fn area(self) -> int; }
+        struct Square { side: int }
+        struct Circle { r: int }
+        impl Shape for Square {
+            fn area(self) -> int { return self.side; }
+        }
+        fn f(d: dyn Drawable) {}
+        fn g(s: dyn Shape) -> int { return s.perimeter(); }
+        fn h(s: dyn Shape) -> int { return s.area(1); }
+        fn k(s: dyn Shape) -> int { return s.area(); }
+        fn main() -> int {
+            let s: dyn Shape;
+            s = Circle { r: 1 };
+            return k(Square { side: 2 });
+        }
+    "#;
+    let (_, diags) = check(sources);
+    let msgs: Vec<&str> = diags.iter().map(|d| &d.message[..]).collect();
+    assert_eq!(msgs, vec![
+        "cannot find interface `Drawable`",
+        "no method named `perimeter` found for `dyn Shape`",
+        "no overload of `area` takes (int)",
+        "mismatched types",
+    ]);
+
+    let sources = r#"
+        interface A { fn a(self) -> int; }
+        interface B { fn b(self) -> int; }
+        struct S { x: int }
+        impl A for S { fn a(self) -> int { return 1; } }
+        impl B for S { fn b(self) -> int { return 2; } }
+        fn f(v: dyn A) -> int { return v.a(); }
+        fn f(v: dyn B) -> int { return v.b(); }
+        fn main() -> int { return f(S { x: 0 }); }
+    "#;
+    assert_eq!(check_src(sources), vec![E_AMBIGUOUS]);
+    let (_, diags) = check(sources);
+    assert_eq!(diags[0].message, "call to `f` is ambiguous");
+    assert_eq!(diags[0].notes, vec!["candidate: f(dyn A)", "candidate: f(dyn B)"]);
+}
+
+#[test]
+fn generic_check_test() {
+    let sources = r#"
+        struct Pair<T> { a: T, b: T }
+        struct Tag<T> { id: int }
+        struct Point { x: int }
+        fn max<T>(a: T, b: T) -> T {
+            if a > b { return a; }
+            return b;
+        }
+        fn f(p: Pair) {}
+        fn g(p: Pair<int, int>) {}
+        fn h(p: Point<int>) {}
+        fn main() -> int {
+            let t = Tag { id: 1 };
+            let p = Pair { a: 1, b: 1.5 };
+            let q = Pair { a: 1, b: 2 };
+            let r: Pair<float>;
+            r = q;
+            return max(1, 2);
+        }
+        fn g() {}
+        fn v() { max(g(), g()); }
+    "#;
+    let (_, diags) = check(sources);
+    let msgs: Vec<&str> = diags.iter().map(|d| &d.message[..]).collect();
+    assert_eq!(msgs, vec![
+        "missing type arguments for generic struct `Pair`",
+        "struct `Pair` takes 1 type argument(s) but 2 were supplied",
+        "unknown generic struct `Point`",
+        "cannot infer type argument `T` of `Tag`",
+        "mismatched types",
+        "mismatched types",
+        "expression has no value",
+    ]);
+
+    // an instance is only checked once the module is otherwise sound
+    let sources = r#"
+        struct Point { x: int }
+        fn max<T>(a: T, b: T) -> T {
+            if a > b { return a; }
+            return b;
+        }
+        fn main() -> int {
+            max(Point { x: 1 }, Point { x: 2 });
+            return max(1, 2);
+        }
+    "#;
+    let (_, diags) = check(sources);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].message, "cannot apply binary operator `>` to type `Point`");
+    assert_eq!(diags[0].labels[1].message, "required by this instantiation");
+
+    // polymorphic recursion
+    let sources = r#"
+        struct Pair<T> { a: T, b: T }
+        fn f<T>(x: T, n: int) -> int {
+            if n == 0 { return 0; }
+            return f(Pair { a: x, b: x }, n - 1);
+        }
+        fn main() -> int { return f(1, 3); }
+    "#;
+    assert_eq!(check_src(sources), vec![E_RECURSION_LIMIT]);
+}
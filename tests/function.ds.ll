@@ -1,81 +1,83 @@
 ; ModuleID = '__module'
 source_filename = "__module"
 
-define i64 @foo1(i64, i64) {
+define i64 @_D4foo1ii(i64 %0, i64 %1) {
 entry:
-  %a = alloca i64
-  store i64 %0, i64* %a
-  %b = alloca i64
-  store i64 %1, i64* %b
-  %c = alloca i64
-  %2 = load i64, i64* %a
+  %a = alloca i64, align 8
+  store i64 %0, i64* %a, align 4
+  %b = alloca i64, align 8
+  store i64 %1, i64* %b, align 4
+  %c = alloca i64, align 8
+  %2 = load i64, i64* %a, align 4
   %3 = add i64 %2, 1001
-  store i64 %3, i64* %c
-  %d = alloca i64
-  %ok = alloca float
-  store float 0x405EDD2F20000000, float* %ok
-  %4 = load float, float* %ok
+  store i64 %3, i64* %c, align 4
+  %d = alloca i64, align 8
+  %ok = alloca float, align 4
+  store float 0x405EDD2F20000000, float* %ok, align 4
+  %4 = load float, float* %ok, align 4
   %5 = fcmp ogt float %4, 0x405907DF40000000
   br i1 %5, label %"if:then", label %"if:else"
 
 "if:then":                                        ; preds = %entry
-  %val = alloca float
-  store float 0x405ECF5C20000000, float* %val
-  %6 = load i64, i64* %b
+  %val = alloca float, align 4
+  store float 0x405ECF5C20000000, float* %val, align 4
+  %6 = load i64, i64* %b, align 4
   %7 = add i64 %6, 1992
-  %8 = load i64, i64* %c
+  %8 = load i64, i64* %c, align 4
   %9 = add i64 %7, %8
-  %10 = load i64, i64* %a
+  %10 = load i64, i64* %a, align 4
   %11 = add i64 %9, %10
-  store i64 %11, i64* %d
-  %12 = load float, float* %val
+  store i64 %11, i64* %d, align 4
+  %12 = load float, float* %val, align 4
   %13 = fadd float %12, 0x3FEBD70A40000000
-  store float %13, float* %val
+  store float %13, float* %val, align 4
   br label %"if:merge"
 
 "if:else":                                        ; preds = %entry
+  %14 = load i64, i64* %b, align 4
+  store i64 %14, i64* %d, align 4
   br label %"if:merge"
 
 "if:merge":                                       ; preds = %"if:else", %"if:then"
-  %14 = load i64, i64* %c
-  %15 = icmp sgt i64 %14, 100
-  br i1 %15, label %"if:then1", label %"if:else2"
+  %15 = load i64, i64* %c, align 4
+  %16 = icmp sgt i64 %15, 100
+  br i1 %16, label %"if:then1", label %"if:else2"
 
 "if:then1":                                       ; preds = %"if:merge"
-  %bv = alloca i64
-  store i64 1002, i64* %bv
-  %16 = load i64, i64* %bv
-  %17 = load i64, i64* %c
-  %18 = add i64 %16, %17
-  store i64 %18, i64* %c
+  %bv = alloca i64, align 8
+  store i64 1002, i64* %bv, align 4
+  %17 = load i64, i64* %bv, align 4
+  %18 = load i64, i64* %c, align 4
+  %19 = add i64 %17, %18
+  store i64 %19, i64* %c, align 4
   br label %"if:merge3"
 
 "if:else2":                                       ; preds = %"if:merge"
   br label %"if:merge3"
 
 "if:merge3":                                      ; preds = %"if:else2", %"if:then1"
-  %19 = load i64, i64* %c
-  %20 = load i64, i64* %d
-  %21 = add i64 %19, %20
-  store i64 %21, i64* %a
-  %22 = load i64, i64* %a
-  ret i64 %22
+  %20 = load i64, i64* %c, align 4
+  %21 = load i64, i64* %d, align 4
+  %22 = add i64 %20, %21
+  store i64 %22, i64* %a, align 4
+  %23 = load i64, i64* %a, align 4
+  ret i64 %23
 }
 
-define i1 @foo2(i64) {
+define i1 @_D4foo2i(i64 %0) {
 entry:
-  %a = alloca i64
-  store i64 %0, i64* %a
-  %1 = load i64, i64* %a
+  %a = alloca i64, align 8
+  store i64 %0, i64* %a, align 4
+  %1 = load i64, i64* %a, align 4
   %2 = icmp eq i64 %1, 100
   ret i1 %2
 }
 
-define i64 @fact(i64) {
+define i64 @_D4facti(i64 %0) {
 entry:
-  %n = alloca i64
-  store i64 %0, i64* %n
-  %1 = load i64, i64* %n
+  %n = alloca i64, align 8
+  store i64 %0, i64* %n, align 4
+  %1 = load i64, i64* %n, align 4
   %2 = icmp eq i64 %1, 1
   br i1 %2, label %"if:then", label %"if:else"
 
@@ -83,50 +85,50 @@ entry:
   ret i64 1
 
 "if:else":                                        ; preds = %entry
-  %3 = load i64, i64* %n
+  %3 = load i64, i64* %n, align 4
   %4 = sub i64 %3, 1
-  %5 = call i64 @fact(i64 %4)
-  %6 = load i64, i64* %n
+  %5 = call i64 @_D4facti(i64 %4)
+  %6 = load i64, i64* %n, align 4
   %7 = mul i64 %5, %6
   ret i64 %7
 }
 
 define i64 @main() {
 entry:
-  %a = alloca i64
-  store i64 1093, i64* %a
-  %b = alloca i64
-  %0 = load i64, i64* %a
-  %1 = call i64 @foo1(i64 %0, i64 100)
+  %a = alloca i64, align 8
+  store i64 1093, i64* %a, align 4
+  %b = alloca i64, align 8
+  %0 = load i64, i64* %a, align 4
+  %1 = call i64 @_D4foo1ii(i64 %0, i64 100)
   %2 = add i64 %1, 123
-  %3 = load i64, i64* %a
-  %4 = call i64 @foo1(i64 %3, i64 12)
+  %3 = load i64, i64* %a, align 4
+  %4 = call i64 @_D4foo1ii(i64 %3, i64 12)
   %5 = add i64 %2, %4
-  store i64 %5, i64* %a
-  %6 = load i64, i64* %a
+  store i64 %5, i64* %a, align 4
+  %6 = load i64, i64* %a, align 4
   %7 = add i64 %6, 120
-  %8 = call i64 @foo1(i64 123, i64 %7)
-  store i64 %8, i64* %b
+  %8 = call i64 @_D4foo1ii(i64 123, i64 %7)
+  store i64 %8, i64* %b, align 4
   br label %"while:cond"
 
 "while:cond":                                     ; preds = %"while:body", %entry
-  %9 = load i64, i64* %b
-  %10 = add i64 %9, 100
-  %11 = load i64, i64* %a
-  %12 = icmp sgt i64 %11, %10
+  %9 = load i64, i64* %a, align 4
+  %10 = load i64, i64* %b, align 4
+  %11 = add i64 %10, 100
+  %12 = icmp sgt i64 %9, %11
   br i1 %12, label %"while:body", label %"while:merge"
 
 "while:body":                                     ; preds = %"while:cond"
-  %13 = load i64, i64* %a
-  %14 = load i64, i64* %b
-  %15 = call i64 @foo1(i64 %13, i64 %14)
-  %16 = load i64, i64* %a
+  %13 = load i64, i64* %a, align 4
+  %14 = load i64, i64* %b, align 4
+  %15 = call i64 @_D4foo1ii(i64 %13, i64 %14)
+  %16 = load i64, i64* %a, align 4
   %17 = add i64 %16, %15
-  store i64 %17, i64* %b
+  store i64 %17, i64* %b, align 4
   br label %"while:cond"
 
 "while:merge":                                    ; preds = %"while:cond"
   ret i64 0
 }
-%StrOk = type { i64, i64 }
 %Dingli = type { i64, i64 }
+%StrOk = type { i64, i64 }
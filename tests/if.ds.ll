@@ -1,24 +1,27 @@
 ; ModuleID = '__module'
+source_filename = "__module"
 
-define i64 @"main\AB\7F"() {
+define i64 @main() {
 entry:
-  %a = alloca i64
-  store i64 1093, i64* %a
-  %retval = alloca i64
-  %0 = load i64, i64* %a
+  %a = alloca i64, align 8
+  store i64 1093, i64* %a, align 4
+  %retval = alloca i64, align 8
+  %0 = load i64, i64* %a, align 4
   %1 = icmp sgt i64 %0, 92
-  br i1 %1, label %if-then, label %if-else
+  br i1 %1, label %"if:then", label %"if:else"
 
-if-then:                                          ; preds = %entry
-  %2 = load i64, i64* %a
+"if:then":                                        ; preds = %entry
+  %2 = load i64, i64* %a, align 4
   %3 = add i64 %2, 13
-  store i64 %3, i64* %retval
-  br label %merge
+  store i64 %3, i64* %retval, align 4
+  br label %"if:merge"
 
-if-else:                                          ; preds = %entry
-  br label %merge
+"if:else":                                        ; preds = %entry
+  %4 = load i64, i64* %a, align 4
+  store i64 %4, i64* %retval, align 4
+  br label %"if:merge"
 
-merge:                                            ; preds = %if-else, %if-then
-  %4 = load i64, i64* %retval
-  ret i64 %4
+"if:merge":                                       ; preds = %"if:else", %"if:then"
+  %5 = load i64, i64* %retval, align 4
+  ret i64 %5
 }
@@ -1,25 +1,27 @@
 	.text
-	.file	"tests/if.ds.ll"
-	.globl	"main"
-	.align	16, 0x90
-	.type	"main",@function
-"main":                               # @"main\D3\7F"
+	.file	"__module"
+	.globl	main                            # -- Begin function main
+	.p2align	4, 0x90
+	.type	main,@function
+main:                                   # @main
 	.cfi_startproc
-# BB#0:                                 # %entry
-	movq	$1093, -8(%rsp)         # imm = 0x445
+# %bb.0:                                # %entry
+	movq	$1093, -8(%rsp)                 # imm = 0x445
 	xorl	%eax, %eax
 	testb	%al, %al
 	jne	.LBB0_2
-# BB#1:                                 # %if-then
+# %bb.1:                                # %"if:then"
 	movq	-8(%rsp), %rax
 	addq	$13, %rax
+	jmp	.LBB0_3
+.LBB0_2:                                # %"if:else"
+	movq	-8(%rsp), %rax
+.LBB0_3:                                # %"if:merge"
 	movq	%rax, -16(%rsp)
-.LBB0_2:                                # %merge
 	movq	-16(%rsp), %rax
 	retq
 .Lfunc_end0:
-	.size	"main", .Lfunc_end0-"main"
+	.size	main, .Lfunc_end0-main
 	.cfi_endproc
-
-
+                                        # -- End function
 	.section	".note.GNU-stack","",@progbits